@@ -141,49 +141,531 @@
 // fields and valid values. Continue to treat cid as optional. In your
 // batch file, how many passports are valid?
 
+use crate::groups::{groups, Groups};
+use crate::json::json_string;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, Write};
 use std::path::Path;
 
+const REQUIRED_KEYS: &[&str] = &["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
+const CANONICAL_KEYS: &[&str] = &["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid", "cid"];
+
+#[derive(Debug, PartialEq)]
+pub enum PassportError {
+    MalformedToken { line: usize, token: String },
+    DuplicateKey { line: usize, key: String },
+    InvalidJson { position: usize },
+    InvalidCsv { line: usize },
+    // A field with an empty key, or whitespace or ':' in its key or
+    // value, has no batch form.
+    NotBatchable { key: String },
+    Io(io::ErrorKind),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum HeightUnit {
+    Cm,
+    In,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Passport {
+    fields: HashMap<String, String>,
+}
+
+impl Passport {
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(String::as_str)
+    }
+
+    // Known fields in puzzle order, then any others alphabetically.
+    pub fn keys(&self) -> Vec<&str> {
+        let mut keys: Vec<&str> = self.fields.keys().map(String::as_str).collect();
+        keys.sort_by_key(|key| (canonical_position(key), *key));
+        keys
+    }
+
+    pub fn normalize(&self, unit: HeightUnit) -> Passport {
+        let mut fields = self.fields.clone();
+        if let Some(hcl) = fields.get_mut("hcl") {
+            if hcl.starts_with('#') {
+                *hcl = hcl.to_lowercase();
+            }
+        }
+        if let Some(pid) = fields.get_mut("pid") {
+            if !pid.is_empty() && pid.len() < 9 && pid.chars().all(|c| c.is_ascii_digit()) {
+                *pid = format!("{:0>9}", pid);
+            }
+        }
+        if let Some(hgt) = fields.get_mut("hgt") {
+            if let Some(converted) = convert_height(hgt, unit) {
+                *hgt = converted;
+            }
+        }
+        Passport { fields }
+    }
+
+    pub fn to_batch(&self) -> Result<String, PassportError> {
+        let breaks_token = |s: &str| s.contains(|c: char| c.is_whitespace() || c == ':');
+        let tokens = self
+            .keys()
+            .into_iter()
+            .map(|key| {
+                let val = &self.fields[key];
+                if key.is_empty() || breaks_token(key) || breaks_token(val) {
+                    Err(PassportError::NotBatchable {
+                        key: key.to_string(),
+                    })
+                } else {
+                    Ok(format!("{}:{}", key, val))
+                }
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(tokens.join(" "))
+    }
+
+    fn add_line(&mut self, line: usize, text: &str) -> Result<(), PassportError> {
+        for token in text.split_whitespace() {
+            let (key, val) = match token.split_once(':') {
+                Some((key, val)) if !key.is_empty() && !val.contains(':') => (key, val),
+                _ => {
+                    return Err(PassportError::MalformedToken {
+                        line,
+                        token: token.to_string(),
+                    })
+                }
+            };
+            if self.fields.contains_key(key) {
+                return Err(PassportError::DuplicateKey {
+                    line,
+                    key: key.to_string(),
+                });
+            }
+            self.fields.insert(key.to_string(), val.to_string());
+        }
+        Ok(())
+    }
+
+    pub fn has_required_fields(&self) -> bool {
+        REQUIRED_KEYS.iter().all(|k| self.fields.contains_key(*k))
+    }
+
+    pub fn is_valid(&self) -> bool {
+        REQUIRED_KEYS
+            .iter()
+            .all(|k| self.get(k).is_some_and(|val| is_valid(k, val)))
+    }
+}
+
 pub fn part1() -> usize {
-    let path = Path::new("day4-input.txt");
-    let input = std::fs::read_to_string(path).expect("read");
-    let passports = input.as_str().split("\n\n");
-    let all_keys = &["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
-    passports
-        .map(|passport| {
-            all_keys
-                .iter()
-                .all(|k| get_keys(passport).any(|key| &key == k))
-        })
-        .filter(|b| *b)
+    let file = File::open(Path::new("day4-input.txt")).expect("open");
+    passports(io::BufReader::new(file))
+        .filter(|passport| matches!(passport, Ok(p) if p.has_required_fields()))
         .count()
 }
 
-fn get_keys(passport: &str) -> impl Iterator<Item = &str> {
-    passport
-        .split_whitespace()
-        .map(|p| p.split(':').next().expect("next"))
+pub fn part2() -> usize {
+    let file = File::open(Path::new("day4-input.txt")).expect("open");
+    passports(io::BufReader::new(file))
+        .filter(|passport| matches!(passport, Ok(p) if p.is_valid()))
+        .count()
 }
 
-pub fn part2() -> usize {
-    let path = Path::new("day4-input.txt");
-    let input = std::fs::read_to_string(path).expect("read");
-    let passports = input.as_str().split("\n\n");
-    let all_keys = &["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
-    passports
+pub fn parse_batch(input: &str) -> Vec<Result<Passport, PassportError>> {
+    passports(input.as_bytes()).collect()
+}
+
+// Yields passports one at a time, holding only the one being read. A
+// malformed passport is reported and parsing carries on with the next
+// one; see `groups` for how passports are split and I/O errors are
+// handled.
+pub fn passports<R: BufRead>(reader: R) -> Passports<R> {
+    Passports {
+        groups: groups(reader.lines()),
+    }
+}
+
+pub struct Passports<R> {
+    groups: Groups<io::Lines<R>>,
+}
+
+impl<R: BufRead> Iterator for Passports<R> {
+    type Item = Result<Passport, PassportError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let lines = match self.groups.next()? {
+            Ok(lines) => lines,
+            Err(e) => return Some(Err(PassportError::Io(e.kind()))),
+        };
+        let mut passport = Passport::default();
+        for (line, text) in lines {
+            if let Err(e) = passport.add_line(line, &text) {
+                return Some(Err(e));
+            }
+        }
+        Some(Ok(passport))
+    }
+}
+
+// Writes one verdict per passport as soon as it is read and returns
+// the number of valid passports.
+pub fn validate<R: BufRead, W: Write>(reader: R, mut writer: W) -> io::Result<usize> {
+    let mut valid = 0;
+    for (index, passport) in passports(reader).enumerate() {
+        match passport {
+            Ok(p) if p.is_valid() => {
+                valid += 1;
+                writeln!(writer, "{}: valid", index + 1)?;
+            }
+            Ok(_) => writeln!(writer, "{}: invalid", index + 1)?,
+            Err(e) => writeln!(writer, "{}: error {:?}", index + 1, e)?,
+        }
+    }
+    Ok(valid)
+}
+
+pub fn write_batch(passports: &[Passport]) -> Result<String, PassportError> {
+    let mut output = String::new();
+    for (index, passport) in passports.iter().enumerate() {
+        if index > 0 {
+            output.push('\n');
+        }
+        output.push_str(&passport.to_batch()?);
+        output.push('\n');
+    }
+    Ok(output)
+}
+
+pub fn to_json(passports: &[Passport]) -> String {
+    let objects: Vec<_> = passports
+        .iter()
         .map(|passport| {
-            all_keys
-                .iter()
-                .all(|k| get_kvs(passport).any(|(key, val)| &key == k && is_valid(key, val)))
+            let members: Vec<_> = passport
+                .keys()
+                .into_iter()
+                .map(|key| {
+                    format!(
+                        "{}:{}",
+                        json_string(key),
+                        json_string(&passport.fields[key])
+                    )
+                })
+                .collect();
+            format!("  {{{}}}", members.join(","))
         })
-        .filter(|b| *b)
-        .count()
+        .collect();
+    if objects.is_empty() {
+        "[]\n".to_string()
+    } else {
+        format!("[\n{}\n]\n", objects.join(",\n"))
+    }
+}
+
+// Reads an array of flat objects whose values are strings (or bare
+// numbers, kept verbatim).
+pub fn from_json(input: &str) -> Result<Vec<Passport>, PassportError> {
+    let mut reader = JsonReader {
+        chars: input.char_indices().peekable(),
+        len: input.len(),
+    };
+    let mut passports = Vec::new();
+    reader.expect('[')?;
+    if !reader.eat(']') {
+        loop {
+            passports.push(reader.object()?);
+            if reader.eat(']') {
+                break;
+            }
+            reader.expect(',')?;
+        }
+    }
+    reader.skip_whitespace();
+    match reader.chars.peek() {
+        None => Ok(passports),
+        Some(_) => Err(reader.error()),
+    }
+}
+
+struct JsonReader<'a> {
+    chars: std::iter::Peekable<std::str::CharIndices<'a>>,
+    len: usize,
+}
+
+impl JsonReader<'_> {
+    fn error(&mut self) -> PassportError {
+        let position = self.chars.peek().map_or(self.len, |(i, _)| *i);
+        PassportError::InvalidJson { position }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.chars.next_if(|(_, c)| c.is_whitespace()).is_some() {}
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        self.skip_whitespace();
+        self.chars.next_if(|(_, c)| *c == expected).is_some()
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), PassportError> {
+        if self.eat(expected) {
+            Ok(())
+        } else {
+            Err(self.error())
+        }
+    }
+
+    fn object(&mut self) -> Result<Passport, PassportError> {
+        let mut passport = Passport::default();
+        self.expect('{')?;
+        if self.eat('}') {
+            return Ok(passport);
+        }
+        loop {
+            self.skip_whitespace();
+            let position = self.chars.peek().map_or(self.len, |(i, _)| *i);
+            let key = self.string()?;
+            self.expect(':')?;
+            let val = self.value()?;
+            if passport.fields.insert(key, val).is_some() {
+                return Err(PassportError::InvalidJson { position });
+            }
+            if self.eat('}') {
+                return Ok(passport);
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn value(&mut self) -> Result<String, PassportError> {
+        self.skip_whitespace();
+        match self.chars.peek() {
+            Some((_, '"')) => self.string(),
+            Some((_, c)) if c.is_ascii_digit() || *c == '-' => self.number(),
+            _ => Err(self.error()),
+        }
+    }
+
+    // -? (0 | [1-9][0-9]*) (. [0-9]+)? ([eE] [+-]? [0-9]+)?, kept as
+    // written.
+    fn number(&mut self) -> Result<String, PassportError> {
+        let mut number = String::new();
+        self.take(&mut number, |c| c == '-');
+        if !self.take(&mut number, |c| c == '0') && self.digits(&mut number) == 0 {
+            return Err(self.error());
+        }
+        if self.take(&mut number, |c| c == '.') && self.digits(&mut number) == 0 {
+            return Err(self.error());
+        }
+        if self.take(&mut number, |c| c == 'e' || c == 'E') {
+            self.take(&mut number, |c| c == '+' || c == '-');
+            if self.digits(&mut number) == 0 {
+                return Err(self.error());
+            }
+        }
+        Ok(number)
+    }
+
+    fn take(&mut self, number: &mut String, accept: impl Fn(char) -> bool) -> bool {
+        match self.chars.next_if(|(_, c)| accept(*c)) {
+            Some((_, c)) => {
+                number.push(c);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn digits(&mut self, number: &mut String) -> usize {
+        let mut count = 0;
+        while self.take(number, |c| c.is_ascii_digit()) {
+            count += 1;
+        }
+        count
+    }
+
+    fn string(&mut self) -> Result<String, PassportError> {
+        if self.chars.next_if(|(_, c)| *c == '"').is_none() {
+            return Err(self.error());
+        }
+        let mut s = String::new();
+        loop {
+            match self.chars.next() {
+                Some((_, '"')) => return Ok(s),
+                Some((i, '\\')) => {
+                    let escaped = match self.chars.next() {
+                        Some((_, '"')) => '"',
+                        Some((_, '\\')) => '\\',
+                        Some((_, '/')) => '/',
+                        Some((_, 'b')) => '\u{8}',
+                        Some((_, 'f')) => '\u{c}',
+                        Some((_, 'n')) => '\n',
+                        Some((_, 'r')) => '\r',
+                        Some((_, 't')) => '\t',
+                        Some((_, 'u')) => {
+                            let hex: String = (0..4)
+                                .filter_map(|_| self.chars.next())
+                                .map(|(_, c)| c)
+                                .collect();
+                            u32::from_str_radix(&hex, 16)
+                                .ok()
+                                .and_then(char::from_u32)
+                                .ok_or(PassportError::InvalidJson { position: i })?
+                        }
+                        _ => return Err(PassportError::InvalidJson { position: i }),
+                    };
+                    s.push(escaped);
+                }
+                Some((_, c)) => s.push(c),
+                None => return Err(self.error()),
+            }
+        }
+    }
+}
+
+// The header lists every key present in any passport; an empty cell
+// is a missing field and a quoted empty cell an empty value.
+pub fn to_csv(passports: &[Passport]) -> String {
+    let mut header: Vec<&str> = passports.iter().flat_map(|p| p.keys()).collect();
+    header.sort_by_key(|key| (canonical_position(key), *key));
+    header.dedup();
+    let mut output = header.join(",");
+    output.push('\n');
+    for passport in passports {
+        let cells: Vec<_> = header
+            .iter()
+            .map(|key| passport.get(key).map_or(String::new(), csv_cell))
+            .collect();
+        output.push_str(&cells.join(","));
+        output.push('\n');
+    }
+    output
+}
+
+fn csv_cell(s: &str) -> String {
+    if s.is_empty() || s.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+pub fn from_csv(input: &str) -> Result<Vec<Passport>, PassportError> {
+    let mut records = csv_records(input)?.into_iter();
+    let header = match records.next() {
+        Some((_, header)) => header,
+        None => return Ok(Vec::new()),
+    };
+    let mut passports = Vec::new();
+    for (line, cells) in records {
+        if cells.len() != header.len() {
+            return Err(PassportError::InvalidCsv { line });
+        }
+        let mut passport = Passport::default();
+        for (key, val) in header.iter().zip(cells) {
+            let val = match val {
+                Some(val) => val,
+                None => continue,
+            };
+            let key = key.clone().unwrap_or_default();
+            if passport.fields.insert(key, val).is_some() {
+                return Err(PassportError::InvalidCsv { line });
+            }
+        }
+        passports.push(passport);
+    }
+    Ok(passports)
+}
+
+// A record's starting line and cells; a cell is `None` when it is
+// empty and unquoted.
+type CsvRecord = (usize, Vec<Option<String>>);
+
+fn csv_records(input: &str) -> Result<Vec<CsvRecord>, PassportError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut line = 1;
+    let mut start = 1;
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if cell.is_empty() && !quoted => loop {
+                quoted = true;
+                match chars.next() {
+                    Some('"') if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        cell.push('"');
+                    }
+                    Some('"') => break,
+                    Some(c) => {
+                        if c == '\n' {
+                            line += 1;
+                        }
+                        cell.push(c)
+                    }
+                    None => return Err(PassportError::InvalidCsv { line: start }),
+                }
+            },
+            ',' => record.push(take_cell(&mut cell, &mut quoted)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(take_cell(&mut cell, &mut quoted));
+                if record.len() > 1 || record[0].is_some() {
+                    records.push((start, std::mem::take(&mut record)));
+                } else {
+                    record.clear();
+                }
+                line += 1;
+                start = line;
+            }
+            c => cell.push(c),
+        }
+    }
+    if !cell.is_empty() || quoted || !record.is_empty() {
+        record.push(take_cell(&mut cell, &mut quoted));
+        records.push((start, record));
+    }
+    Ok(records)
+}
+
+fn take_cell(cell: &mut String, quoted: &mut bool) -> Option<String> {
+    let value = if cell.is_empty() && !*quoted {
+        None
+    } else {
+        Some(std::mem::take(cell))
+    };
+    *quoted = false;
+    value
+}
+
+fn canonical_position(key: &str) -> usize {
+    CANONICAL_KEYS
+        .iter()
+        .position(|k| *k == key)
+        .unwrap_or(CANONICAL_KEYS.len())
 }
 
-fn get_kvs(passport: &str) -> impl Iterator<Item = (&str, &str)> {
-    passport.split_whitespace().map(|p| {
-        let mut s = p.split(':');
-        (s.next().expect("first"), s.next().expect("second"))
-    })
+fn convert_height(hgt: &str, unit: HeightUnit) -> Option<String> {
+    let (value, from) = if let Some(value) = hgt.strip_suffix("cm") {
+        (value, HeightUnit::Cm)
+    } else if let Some(value) = hgt.strip_suffix("in") {
+        (value, HeightUnit::In)
+    } else {
+        return None;
+    };
+    let value: u64 = value.parse().ok()?;
+    let converted = match (from, unit) {
+        (HeightUnit::Cm, HeightUnit::In) => (value.checked_mul(100)? + 127) / 254,
+        (HeightUnit::In, HeightUnit::Cm) => (value.checked_mul(254)? + 50) / 100,
+        _ => value,
+    };
+    let suffix = match unit {
+        HeightUnit::Cm => "cm",
+        HeightUnit::In => "in",
+    };
+    Some(format!("{}{}", converted, suffix))
 }
 
 fn is_valid(key: &str, val: &str) -> bool {
@@ -191,14 +673,18 @@ fn is_valid(key: &str, val: &str) -> bool {
         "byr" => range_parse(val, 1920, 2002),
         "iyr" => range_parse(val, 2010, 2020),
         "eyr" => range_parse(val, 2020, 2030),
-        "hgt" => match val.split_at(val.len() - 2) {
-            (val, "cm") => range_parse(val, 150, 193),
-            (val, "in") => range_parse(val, 59, 76),
-            _ => false,
-        },
-        "hcl" => match val.split_at(1) {
-            ("#", val) => hex_parse(val),
-            _ => false,
+        "hgt" => {
+            if let Some(val) = val.strip_suffix("cm") {
+                range_parse(val, 150, 193)
+            } else if let Some(val) = val.strip_suffix("in") {
+                range_parse(val, 59, 76)
+            } else {
+                false
+            }
+        }
+        "hcl" => match val.strip_prefix('#') {
+            Some(val) => val.len() == 6 && hex_parse(val),
+            None => false,
         },
         "ecl" => matches!(val, "amb" | "blu" | "brn" | "gry" | "grn" | "hzl" | "oth"),
         "pid" => pid_parse(val),
@@ -240,4 +726,222 @@ mod tests {
     fn test_part2() {
         assert_eq!(186, part2())
     }
+
+    #[test]
+    fn test_parse_batch_blank_lines() {
+        let input = "ecl:gry pid:860033327 \r\nhcl:#fffffd\r\n  \r\niyr:2013\t\n\n\n\nbyr:1929\n";
+        let passports = parse_batch(input);
+        assert_eq!(passports.len(), 3);
+        let first = passports[0].as_ref().expect("first");
+        assert_eq!(first.get("hcl"), Some("#fffffd"));
+        assert_eq!(
+            passports[1].as_ref().expect("second").get("iyr"),
+            Some("2013")
+        );
+    }
+
+    #[test]
+    fn test_parse_batch_errors() {
+        let input = "ecl:gry pid\n\nbyr:1937\niyr:2017 byr:1940\n\nhgt:1cm";
+        let passports = parse_batch(input);
+        assert_eq!(
+            passports[0],
+            Err(PassportError::MalformedToken {
+                line: 1,
+                token: "pid".to_string()
+            })
+        );
+        assert_eq!(
+            passports[1],
+            Err(PassportError::DuplicateKey {
+                line: 4,
+                key: "byr".to_string()
+            })
+        );
+        assert!(passports[2].is_ok());
+    }
+
+    #[test]
+    fn test_is_valid_hostile_values() {
+        for (key, val) in &[
+            ("hgt", ""),
+            ("hgt", "m"),
+            ("hgt", "cm"),
+            ("hgt", "é"),
+            ("hcl", ""),
+            ("hcl", "#"),
+            ("hcl", "é"),
+            ("hcl", "#123abcd"),
+            ("pid", ""),
+        ] {
+            assert!(!is_valid(key, val), "{}:{}", key, val);
+        }
+        assert!(is_valid("hgt", "60in"));
+        assert!(is_valid("hcl", "#123abc"));
+    }
+
+    #[test]
+    fn test_validate_streams_verdicts() {
+        let input = "hcl:#zzzzzz\n\nbyr:1\nbyr:2\n\npid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980\nhcl:#623a2f\n";
+        let mut output = Vec::new();
+        assert_eq!(
+            validate(
+                io::BufReader::with_capacity(4, input.as_bytes()),
+                &mut output
+            )
+            .expect("validate"),
+            1
+        );
+        assert_eq!(
+            String::from_utf8(output).expect("utf8"),
+            "1: invalid\n2: error DuplicateKey { line: 4, key: \"byr\" }\n3: valid\n"
+        );
+        let invalid_utf8: &[u8] = b"byr:1937\n\xff\n\niyr:2017\n";
+        let results: Vec<_> = passports(invalid_utf8).collect();
+        assert_eq!(results.len(), 2);
+        assert_eq!(
+            results[0],
+            Err(PassportError::Io(io::ErrorKind::InvalidData))
+        );
+        assert_eq!(results[1].as_ref().map(|p| p.get("iyr")), Ok(Some("2017")));
+
+        struct Failing;
+        impl io::Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("broken"))
+            }
+        }
+        let results: Vec<_> = passports(io::BufReader::new(Failing)).collect();
+        assert_eq!(results, vec![Err(PassportError::Io(io::ErrorKind::Other))]);
+        let mut output = Vec::new();
+        assert_eq!(
+            validate(io::BufReader::new(Failing), &mut output).expect("validate"),
+            0
+        );
+        assert_eq!(
+            String::from_utf8(output).expect("utf8"),
+            "1: error Io(Other)\n"
+        );
+    }
+
+    const EXAMPLE: &str = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd
+byr:1937 iyr:2017 cid:147 hgt:183cm
+
+iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884
+hcl:#cfa07d byr:1929
+
+hcl:#ae17e1 iyr:2013
+eyr:2024
+ecl:brn pid:760753108 byr:1931
+hgt:179cm
+
+hcl:#cfa07d eyr:2025 pid:166559648
+iyr:2011 ecl:brn hgt:59in
+";
+
+    fn example() -> Vec<Passport> {
+        parse_batch(EXAMPLE)
+            .into_iter()
+            .map(|p| p.expect("passport"))
+            .collect()
+    }
+
+    #[test]
+    fn test_write_batch_round_trip() {
+        let passports = example();
+        let batch = write_batch(&passports).expect("batch");
+        assert!(batch.starts_with(
+            "byr:1937 iyr:2017 eyr:2020 hgt:183cm hcl:#fffffd ecl:gry pid:860033327 cid:147\n\n"
+        ));
+        let reparsed: Vec<_> = parse_batch(&batch)
+            .into_iter()
+            .map(|p| p.expect("passport"))
+            .collect();
+        assert_eq!(passports, reparsed);
+    }
+
+    #[test]
+    fn test_json_csv_round_trip() {
+        let mut passports = example();
+        passports[0]
+            .fields
+            .insert("note".to_string(), "say \"hi\", ok".to_string());
+        assert_eq!(from_json(&to_json(&passports)), Ok(passports.clone()));
+        assert_eq!(from_csv(&to_csv(&passports)), Ok(passports.clone()));
+        assert_eq!(from_json("[]"), Ok(vec![]));
+        assert_eq!(
+            from_json(r#"[{"pid":-0.5e+3,"byr":1937}]"#).expect("json")[0].get("pid"),
+            Some("-0.5e+3")
+        );
+        for (input, position) in &[
+            (r#"[{"pid":--1e.e}]"#, 9),
+            (r#"[{"pid":01}]"#, 9),
+            (r#"[{"pid":1.}]"#, 10),
+            (r#"[{"pid":1e}]"#, 10),
+        ] {
+            assert_eq!(
+                from_json(input),
+                Err(PassportError::InvalidJson {
+                    position: *position
+                })
+            );
+        }
+        let empty = vec![parse_batch("pid: ecl:gry").remove(0).expect("passport")];
+        assert_eq!(to_csv(&empty), "ecl,pid\ngry,\"\"\n");
+        assert_eq!(from_csv(&to_csv(&empty)), Ok(empty));
+        assert_eq!(
+            from_csv("pid,ecl\n,\"\"\n").expect("csv").remove(0).keys(),
+            vec!["ecl"]
+        );
+
+        let imported = from_json(&to_json(&example())).expect("json");
+        let batch = write_batch(&imported).expect("batch");
+        let reparsed: Vec<_> = parse_batch(&batch)
+            .into_iter()
+            .map(|p| p.expect("passport"))
+            .collect();
+        assert_eq!(reparsed, example());
+        assert_eq!(
+            write_batch(&passports),
+            Err(PassportError::NotBatchable {
+                key: "note".to_string()
+            })
+        );
+        let imported = from_json(r#"[{"pid":"a b","note":"x:y"}]"#).expect("json");
+        assert_eq!(
+            write_batch(&imported),
+            Err(PassportError::NotBatchable {
+                key: "pid".to_string()
+            })
+        );
+        let imported = from_json(r#"[{"":"1"}]"#).expect("json");
+        assert_eq!(
+            imported[0].to_batch(),
+            Err(PassportError::NotBatchable { key: String::new() })
+        );
+        assert_eq!(
+            from_json("[{\"pid\": 1,}]"),
+            Err(PassportError::InvalidJson { position: 11 })
+        );
+        assert_eq!(
+            from_csv("pid,ecl\r\n\r\n1\n"),
+            Err(PassportError::InvalidCsv { line: 3 })
+        );
+    }
+
+    #[test]
+    fn test_normalize() {
+        let passport = parse_batch("hcl:#AbC123 pid:1234 hgt:74in")
+            .remove(0)
+            .expect("passport");
+        let normalized = passport.normalize(HeightUnit::Cm);
+        assert_eq!(
+            normalized.to_batch(),
+            Ok("hgt:188cm hcl:#abc123 pid:000001234".to_string())
+        );
+        assert_eq!(
+            normalized.normalize(HeightUnit::In).get("hgt"),
+            Some("74in")
+        );
+    }
 }
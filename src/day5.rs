@@ -63,6 +63,204 @@ use std::fs::File;
 use std::io::{self, BufRead};
 use std::path::Path;
 
+#[derive(Debug, PartialEq)]
+pub enum PassError {
+    Length(usize),
+    Letter { position: usize, found: char },
+    OutOfRange,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum AircraftError {
+    NotPowerOfTwo(usize),
+    TooLarge,
+    AmbiguousLetters,
+}
+
+// A cabin of `rows` x `cols` seats, both powers of two, so a boarding
+// pass is log2(rows) row letters followed by log2(cols) column
+// letters.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Aircraft {
+    rows: usize,
+    cols: usize,
+    front: char,
+    back: char,
+    left: char,
+    right: char,
+}
+
+impl Default for Aircraft {
+    fn default() -> Self {
+        Aircraft::new(128, 8).expect("default")
+    }
+}
+
+impl Aircraft {
+    pub fn new(rows: usize, cols: usize) -> Result<Self, AircraftError> {
+        for n in &[rows, cols] {
+            if !n.is_power_of_two() {
+                return Err(AircraftError::NotPowerOfTwo(*n));
+            }
+        }
+        if rows.checked_mul(cols).is_none() {
+            return Err(AircraftError::TooLarge);
+        }
+        Ok(Aircraft {
+            rows,
+            cols,
+            front: 'F',
+            back: 'B',
+            left: 'L',
+            right: 'R',
+        })
+    }
+
+    pub fn with_letters(
+        self,
+        front: char,
+        back: char,
+        left: char,
+        right: char,
+    ) -> Result<Self, AircraftError> {
+        if front == back || left == right {
+            return Err(AircraftError::AmbiguousLetters);
+        }
+        Ok(Aircraft {
+            front,
+            back,
+            left,
+            right,
+            ..self
+        })
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    pub fn seats(&self) -> usize {
+        self.rows * self.cols
+    }
+
+    fn row_bits(&self) -> usize {
+        self.rows.trailing_zeros() as usize
+    }
+
+    fn col_bits(&self) -> usize {
+        self.cols.trailing_zeros() as usize
+    }
+
+    pub fn seat(&self, row: usize, col: usize) -> Result<BoardingPass, PassError> {
+        if row < self.rows && col < self.cols {
+            Ok(BoardingPass { row, col })
+        } else {
+            Err(PassError::OutOfRange)
+        }
+    }
+
+    pub fn seat_from_id(&self, id: usize) -> Result<BoardingPass, PassError> {
+        self.seat(id / self.cols, id % self.cols)
+    }
+
+    // Passes come from any aircraft, so a seat may not exist on this
+    // one.
+    fn check(&self, pass: &BoardingPass) -> Result<(), PassError> {
+        self.seat(pass.row, pass.col).map(|_| ())
+    }
+
+    pub fn seat_id(&self, pass: &BoardingPass) -> Result<usize, PassError> {
+        self.check(pass)?;
+        Ok(pass.row * self.cols + pass.col)
+    }
+
+    // Each letter is one bit, most significant first: front and left
+    // are 0, back and right are 1.
+    pub fn decode(&self, pass: &str) -> Result<BoardingPass, PassError> {
+        let row_bits = self.row_bits();
+        let len = pass.chars().count();
+        if len != row_bits + self.col_bits() {
+            return Err(PassError::Length(len));
+        }
+        let mut row = 0;
+        let mut col = 0;
+        for (position, found) in pass.chars().enumerate() {
+            if position < row_bits && found == self.front {
+                row <<= 1;
+            } else if position < row_bits && found == self.back {
+                row = row << 1 | 1;
+            } else if position >= row_bits && found == self.left {
+                col <<= 1;
+            } else if position >= row_bits && found == self.right {
+                col = col << 1 | 1;
+            } else {
+                return Err(PassError::Letter { position, found });
+            }
+        }
+        Ok(BoardingPass { row, col })
+    }
+
+    pub fn encode(&self, pass: &BoardingPass) -> Result<String, PassError> {
+        self.check(pass)?;
+        let row = (0..self.row_bits()).rev().map(|bit| {
+            if pass.row >> bit & 1 == 0 {
+                self.front
+            } else {
+                self.back
+            }
+        });
+        let col = (0..self.col_bits()).rev().map(|bit| {
+            if pass.col >> bit & 1 == 0 {
+                self.left
+            } else {
+                self.right
+            }
+        });
+        Ok(row.chain(col).collect())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BoardingPass {
+    row: usize,
+    col: usize,
+}
+
+// Shorthands for the puzzle's 128 x 8 aircraft.
+impl BoardingPass {
+    pub fn new(row: usize, col: usize) -> Result<Self, PassError> {
+        Aircraft::default().seat(row, col)
+    }
+
+    pub fn from_id(id: usize) -> Result<Self, PassError> {
+        Aircraft::default().seat_from_id(id)
+    }
+
+    pub fn decode(pass: &str) -> Result<Self, PassError> {
+        Aircraft::default().decode(pass)
+    }
+
+    pub fn encode(&self) -> Result<String, PassError> {
+        Aircraft::default().encode(self)
+    }
+
+    pub fn id(&self) -> Result<usize, PassError> {
+        Aircraft::default().seat_id(self)
+    }
+
+    pub fn row(&self) -> usize {
+        self.row
+    }
+
+    pub fn col(&self) -> usize {
+        self.col
+    }
+}
+
 pub fn part1() -> usize {
     let file = File::open(Path::new("day5-input.txt")).expect("open");
     io::BufReader::new(file)
@@ -73,27 +271,9 @@ pub fn part1() -> usize {
 }
 
 fn seat_id(pass: &str) -> usize {
-    let (vertical, horizontal) = pass.split_at(7);
-    let row = reduce(vertical, 'F', 0, 127);
-    let col = reduce(horizontal, 'L', 0, 7);
-    row * 8 + col
-}
-
-fn reduce(chars: &str, lchar: char, min: usize, max: usize) -> usize {
-    let (first, second) = reduce_h(chars, lchar, min, max);
-    assert_eq!(first, second);
-    first
-}
-
-fn reduce_h(chars: &str, lchar: char, min: usize, max: usize) -> (usize, usize) {
-    if max - min == 1 {
-        let last = if chars.starts_with(lchar) { min } else { max };
-        (last, last)
-    } else if chars.starts_with(lchar) {
-        reduce_h(&chars[1..], lchar, min, max - (max - min) / 2 - 1)
-    } else {
-        reduce_h(&chars[1..], lchar, min + (max - min) / 2 + 1, max)
-    }
+    BoardingPass::decode(pass)
+        .and_then(|pass| pass.id())
+        .expect("decode")
 }
 
 // --- Part Two ---
@@ -113,17 +293,110 @@ fn reduce_h(chars: &str, lchar: char, min: usize, max: usize) -> (usize, usize)
 
 pub fn part2() -> usize {
     let file = File::open(Path::new("day5-input.txt")).expect("open");
-    let mut seat_ids: Vec<_> = io::BufReader::new(file)
+    let passes = io::BufReader::new(file)
         .lines()
-        .map(|pass| seat_id(&pass.expect("pass")))
-        .collect();
-    seat_ids.sort_unstable();
-    let (f, _) = seat_ids[..seat_ids.len()]
-        .iter()
-        .zip(seat_ids[1..].iter())
-        .find(|(f, s)| *s - *f > 1)
-        .unwrap();
-    f + 1
+        .map(|pass| BoardingPass::decode(&pass.expect("pass")).expect("decode"));
+    let map = SeatMap::new(Aircraft::default(), passes);
+    map.free_seats()
+        .into_iter()
+        .find(|id| map.is_occupied(id - 1) && map.is_occupied(id + 1))
+        .expect("seat")
+}
+
+// Seats before the first and after the last occupied seat are taken
+// not to exist on this aircraft; any other empty seat is free. Passes
+// for seats outside the aircraft are set aside rather than counted.
+pub struct SeatMap {
+    aircraft: Aircraft,
+    counts: Vec<usize>,
+    outside: Vec<BoardingPass>,
+}
+
+impl SeatMap {
+    pub fn new(aircraft: Aircraft, passes: impl IntoIterator<Item = BoardingPass>) -> Self {
+        let mut counts = vec![0; aircraft.seats()];
+        let mut outside = Vec::new();
+        for pass in passes {
+            match aircraft.seat_id(&pass) {
+                Ok(id) => counts[id] += 1,
+                Err(_) => outside.push(pass),
+            }
+        }
+        SeatMap {
+            aircraft,
+            counts,
+            outside,
+        }
+    }
+
+    pub fn is_occupied(&self, id: usize) -> bool {
+        self.counts.get(id).is_some_and(|count| *count > 0)
+    }
+
+    fn occupied_range(&self) -> Option<(usize, usize)> {
+        let first = self.counts.iter().position(|count| *count > 0)?;
+        let last = self.counts.iter().rposition(|count| *count > 0)?;
+        Some((first, last))
+    }
+
+    pub fn empty_seats(&self) -> Vec<usize> {
+        (0..self.counts.len())
+            .filter(|id| !self.is_occupied(*id))
+            .collect()
+    }
+
+    pub fn free_seats(&self) -> Vec<usize> {
+        match self.occupied_range() {
+            Some((first, last)) => (first..=last).filter(|id| !self.is_occupied(*id)).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    pub fn nonexistent_seats(&self) -> Vec<usize> {
+        match self.occupied_range() {
+            Some((first, last)) => (0..first).chain(last + 1..self.counts.len()).collect(),
+            None => (0..self.counts.len()).collect(),
+        }
+    }
+
+    // Seat ids on more than one boarding pass, with how many passes.
+    pub fn duplicates(&self) -> Vec<(usize, usize)> {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 1)
+            .map(|(id, count)| (id, *count))
+            .collect()
+    }
+
+    // Passes whose row or column doesn't exist on this aircraft.
+    pub fn outside(&self) -> &[BoardingPass] {
+        &self.outside
+    }
+
+    // One line per row: '#' occupied, '!' duplicated, '.' free and
+    // ' ' nonexistent.
+    pub fn render(&self) -> String {
+        let range = self.occupied_range();
+        let width = (self.aircraft.rows() - 1).to_string().len();
+        let mut output = String::new();
+        for row in 0..self.aircraft.rows() {
+            let seats: String = (0..self.aircraft.cols())
+                .map(|col| {
+                    let id = row * self.aircraft.cols() + col;
+                    match (self.counts[id], range) {
+                        (0, Some((first, last))) if first <= id && id <= last => '.',
+                        (0, _) => ' ',
+                        (1, _) => '#',
+                        _ => '!',
+                    }
+                })
+                .collect();
+            output.push_str(format!("{:>width$} {}", row, seats, width = width).trim_end());
+            output.push('\n');
+        }
+        output
+    }
 }
 
 #[cfg(test)]
@@ -138,6 +411,86 @@ mod tests {
         assert_eq!(seat_id("BBFFBBFRLL"), 820);
     }
 
+    #[test]
+    fn test_boarding_pass_codec() {
+        let pass = BoardingPass::decode("FBFBBFFRLR").expect("decode");
+        assert_eq!((pass.row(), pass.col()), (44, 5));
+        assert_eq!(pass.encode(), Ok("FBFBBFFRLR".to_string()));
+        assert_eq!(
+            BoardingPass::from_id(820).and_then(|pass| pass.encode()),
+            Ok("BBFFBBFRLL".to_string())
+        );
+        let large = Aircraft::new(256, 16).expect("aircraft");
+        let pass = large.seat(200, 9).expect("seat");
+        assert_eq!(pass.encode(), Err(PassError::OutOfRange));
+        assert_eq!(pass.id(), Err(PassError::OutOfRange));
+        assert_eq!(large.seat_id(&pass), Ok(3209));
+        assert_eq!(BoardingPass::new(128, 0), Err(PassError::OutOfRange));
+        assert_eq!(BoardingPass::from_id(1024), Err(PassError::OutOfRange));
+        assert_eq!(BoardingPass::decode("FBF"), Err(PassError::Length(3)));
+        assert_eq!(
+            BoardingPass::decode("FBFBBFLRLR"),
+            Err(PassError::Letter {
+                position: 6,
+                found: 'L'
+            })
+        );
+    }
+
+    #[test]
+    fn test_aircraft_geometry() {
+        let aircraft = Aircraft::new(4, 16)
+            .expect("aircraft")
+            .with_letters('0', '1', '<', '>')
+            .expect("letters");
+        let pass = aircraft.decode("10><<>").expect("decode");
+        assert_eq!((pass.row(), pass.col()), (2, 9));
+        assert_eq!(aircraft.seat_id(&pass), Ok(41));
+        assert_eq!(
+            aircraft.encode(&aircraft.seat_from_id(41).expect("id")),
+            Ok("10><<>".to_string())
+        );
+        assert_eq!(aircraft.seat_from_id(64), Err(PassError::OutOfRange));
+        assert_eq!(
+            aircraft.decode("FB><<>"),
+            Err(PassError::Letter {
+                position: 0,
+                found: 'F'
+            })
+        );
+        assert_eq!(
+            Aircraft::new(100, 8),
+            Err(AircraftError::NotPowerOfTwo(100))
+        );
+        assert_eq!(Aircraft::new(1, 0), Err(AircraftError::NotPowerOfTwo(0)));
+        assert_eq!(
+            Aircraft::default().with_letters('F', 'F', 'L', 'R'),
+            Err(AircraftError::AmbiguousLetters)
+        );
+    }
+
+    #[test]
+    fn test_seat_map() {
+        let aircraft = Aircraft::new(4, 4).expect("aircraft");
+        let mut passes = [3, 4, 5, 5, 8, 10]
+            .iter()
+            .map(|id| aircraft.seat_from_id(*id).expect("id"))
+            .collect::<Vec<_>>();
+        let elsewhere = Aircraft::new(16, 8).expect("aircraft");
+        let elsewhere = vec![
+            elsewhere.seat(9, 0).expect("seat"),
+            elsewhere.seat(0, 4).expect("seat"),
+        ];
+        passes.extend(&elsewhere);
+        let map = SeatMap::new(aircraft, passes);
+        assert_eq!(map.outside(), &elsewhere[..]);
+        assert_eq!(map.free_seats(), vec![6, 7, 9]);
+        assert_eq!(map.nonexistent_seats(), vec![0, 1, 2, 11, 12, 13, 14, 15]);
+        assert_eq!(map.empty_seats().len(), 11);
+        assert_eq!(map.duplicates(), vec![(5, 2)]);
+        assert_eq!(map.render(), "0    #\n1 #!..\n2 #.#\n3\n");
+    }
+
     #[test]
     fn test_part1() {
         assert_eq!(828, part1())
@@ -114,47 +114,405 @@
 // For each group, count the number of questions to which everyone
 // answered "yes". What is the sum of those counts?
 
-use std::collections::HashSet;
+use crate::groups::text_groups;
+use crate::json::json_string;
+use std::collections::{BTreeMap, HashSet};
 use std::path::Path;
 
+// The questions one person answered "yes" to, one bit per question.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Answers(u128);
+
+impl Answers {
+    pub const CAPACITY: usize = 128;
+
+    // Returns false, leaving the answers unchanged, for a question
+    // beyond `CAPACITY`.
+    pub fn insert(&mut self, question: usize) -> bool {
+        if question >= Answers::CAPACITY {
+            return false;
+        }
+        self.0 |= 1 << question;
+        true
+    }
+
+    pub fn contains(&self, question: usize) -> bool {
+        question < Answers::CAPACITY && self.0 >> question & 1 == 1
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn union(&self, other: &Answers) -> Answers {
+        Answers(self.0 | other.0)
+    }
+
+    pub fn intersection(&self, other: &Answers) -> Answers {
+        Answers(self.0 & other.0)
+    }
+}
+
+// How many people in a group answered "yes" to each question.
+#[derive(Debug, PartialEq)]
+pub struct Group {
+    size: usize,
+    counts: [usize; Answers::CAPACITY],
+}
+
+impl Group {
+    pub fn new(people: impl IntoIterator<Item = Answers>) -> Group {
+        let mut group = Group {
+            size: 0,
+            counts: [0; Answers::CAPACITY],
+        };
+        for answers in people {
+            group.size += 1;
+            for (question, count) in group.counts.iter_mut().enumerate() {
+                if answers.contains(question) {
+                    *count += 1;
+                }
+            }
+        }
+        group
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub fn count(&self, question: usize) -> usize {
+        self.counts.get(question).copied().unwrap_or(0)
+    }
+
+    // Questions that at least `k` people (and at least one person)
+    // answered "yes" to.
+    pub fn at_least(&self, k: usize) -> Answers {
+        let mut answers = Answers::default();
+        for (question, count) in self.counts.iter().enumerate() {
+            if *count > 0 && *count >= k {
+                answers.insert(question);
+            }
+        }
+        answers
+    }
+
+    pub fn anyone(&self) -> Answers {
+        self.at_least(1)
+    }
+
+    pub fn everyone(&self) -> Answers {
+        self.at_least(self.size)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum FormError {
+    TooManyQuestions(usize),
+    InvalidQuestion(String),
+    DuplicateQuestion(String),
+    InvalidAnswer {
+        line: usize,
+        column: usize,
+        found: char,
+    },
+}
+
+// The question identifiers a form accepts. Each person's line is split
+// into identifiers, preferring the longest one at each point but
+// backtracking when that leaves a remainder that can't be split, so
+// codes that are prefixes of one another can share a form; whitespace
+// between answers is ignored.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Form {
+    questions: Vec<String>,
+    // Every identifier is one character, so lines split without
+    // backtracking or allocation.
+    single_chars: bool,
+}
+
+impl Form {
+    pub fn new<I, S>(questions: I) -> Result<Form, FormError>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let questions: Vec<String> = questions.into_iter().map(Into::into).collect();
+        if questions.len() > Answers::CAPACITY {
+            return Err(FormError::TooManyQuestions(questions.len()));
+        }
+        let mut seen = HashSet::new();
+        for question in &questions {
+            if question.is_empty() || question.contains(char::is_whitespace) {
+                return Err(FormError::InvalidQuestion(question.clone()));
+            }
+            if !seen.insert(question) {
+                return Err(FormError::DuplicateQuestion(question.clone()));
+            }
+        }
+        let single_chars = questions.iter().all(|q| q.chars().count() == 1);
+        Ok(Form {
+            questions,
+            single_chars,
+        })
+    }
+
+    // The puzzle's form: questions a through z.
+    pub fn letters() -> Form {
+        Form::new((b'a'..=b'z').map(|c| (c as char).to_string())).expect("letters")
+    }
+
+    pub fn questions(&self) -> &[String] {
+        &self.questions
+    }
+
+    // The indices of the questions that match at byte `start`.
+    fn matches<'a>(&'a self, text: &'a str, start: usize) -> impl Iterator<Item = usize> + 'a {
+        (0..self.questions.len())
+            .filter(move |index| text[start..].starts_with(self.questions[*index].as_str()))
+    }
+
+    pub fn parse_person(&self, line: usize, text: &str) -> Result<Answers, FormError> {
+        if self.single_chars {
+            return self.parse_chars(line, text);
+        }
+        let len = text.len();
+        // Where an answer can start, scanning forwards from the start of
+        // the line; the furthest such point is where a bad line fails.
+        let mut reached = vec![false; len + 1];
+        reached[0] = true;
+        for (start, c) in text.char_indices() {
+            if !reached[start] {
+                continue;
+            }
+            if c.is_whitespace() {
+                reached[start + c.len_utf8()] = true;
+            }
+            for index in self.matches(text, start) {
+                reached[start + self.questions[index].len()] = true;
+            }
+        }
+        if !reached[len] {
+            let furthest = (0..len).rev().find(|start| reached[*start]).unwrap_or(0);
+            let found = text[furthest..].chars().next().expect("character");
+            return Err(FormError::InvalidAnswer {
+                line,
+                column: text[..furthest].chars().count() + 1,
+                found,
+            });
+        }
+        // The longest answer at each point that still lets the rest of
+        // the line split, scanning backwards from the end.
+        let mut choice = vec![None; len + 1];
+        let mut splits = vec![false; len + 1];
+        splits[len] = true;
+        for (start, c) in text.char_indices().rev() {
+            if c.is_whitespace() {
+                splits[start] = splits[start + c.len_utf8()];
+                continue;
+            }
+            choice[start] = self
+                .matches(text, start)
+                .filter(|index| splits[start + self.questions[*index].len()])
+                .max_by_key(|index| self.questions[*index].len());
+            splits[start] = choice[start].is_some();
+        }
+        let mut answers = Answers::default();
+        let mut start = 0;
+        while start < len {
+            match choice[start] {
+                Some(index) => {
+                    answers.insert(index);
+                    start += self.questions[index].len();
+                }
+                None => start += text[start..].chars().next().expect("character").len_utf8(),
+            }
+        }
+        Ok(answers)
+    }
+
+    fn parse_chars(&self, line: usize, text: &str) -> Result<Answers, FormError> {
+        let mut answers = Answers::default();
+        for (column, found) in text.chars().enumerate() {
+            if found.is_whitespace() {
+                continue;
+            }
+            match self.questions.iter().position(|q| q.starts_with(found)) {
+                Some(index) => {
+                    answers.insert(index);
+                }
+                None => {
+                    return Err(FormError::InvalidAnswer {
+                        line,
+                        column: column + 1,
+                        found,
+                    })
+                }
+            }
+        }
+        Ok(answers)
+    }
+
+    pub fn parse_groups(&self, input: &str) -> Result<Vec<Group>, FormError> {
+        text_groups(input)
+            .map(|lines| {
+                let people = lines
+                    .into_iter()
+                    .map(|(line, text)| self.parse_person(line, text))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Group::new(people))
+            })
+            .collect()
+    }
+}
+
 pub fn part1() -> usize {
     let path = Path::new("day6-input.txt");
     let input = std::fs::read_to_string(path).expect("read");
-    let answerss = input.as_str().split("\n\n");
-    answerss
-        .map(|answers| {
-            let mut set = HashSet::new();
-            for answer in answers.chars().filter(|c| *c != '\n') {
-                set.insert(answer);
-            }
-            set.len()
-        })
+    Form::letters()
+        .parse_groups(&input)
+        .expect("parse")
+        .iter()
+        .map(|group| group.anyone().len())
         .sum()
 }
 
 pub fn part2() -> usize {
     let path = Path::new("day6-input.txt");
     let input = std::fs::read_to_string(path).expect("read");
-    let answerss = input.as_str().split("\n\n");
-    answerss
-        .map(|answers| {
-            let mut sets = answers.split_whitespace().map(|yess| {
-                let mut set = HashSet::new();
-                for y in yess.chars() {
-                    set.insert(y);
-                }
-                set
-            });
-            let first = sets.next().unwrap();
-            sets.fold(first, |acc, x| {
-                let set: HashSet<char> = acc.intersection(&x).cloned().collect();
-                set
-            })
-            .len()
-        })
+    Form::letters()
+        .parse_groups(&input)
+        .expect("parse")
+        .iter()
+        .map(|group| group.everyone().len())
         .sum()
 }
 
+#[derive(Debug, PartialEq)]
+pub struct QuestionStats {
+    pub question: String,
+    pub groups: usize,
+    pub people: usize,
+    pub unanimous: usize,
+}
+
+#[derive(Debug)]
+pub struct Statistics {
+    pub questions: Vec<QuestionStats>,
+    pub group_sizes: BTreeMap<usize, usize>,
+    pub groups_without_unanimous: Vec<usize>,
+}
+
+impl Statistics {
+    pub fn new(form: &Form, groups: &[Group]) -> Statistics {
+        let questions = (0..form.questions().len())
+            .map(|question| QuestionStats {
+                question: form.questions()[question].clone(),
+                groups: groups.iter().filter(|g| g.count(question) > 0).count(),
+                people: groups.iter().map(|g| g.count(question)).sum(),
+                unanimous: groups
+                    .iter()
+                    .filter(|g| g.everyone().contains(question))
+                    .count(),
+            })
+            .collect();
+        let mut group_sizes = BTreeMap::new();
+        for group in groups {
+            *group_sizes.entry(group.size()).or_insert(0) += 1;
+        }
+        let groups_without_unanimous = groups
+            .iter()
+            .enumerate()
+            .filter(|(_, g)| g.everyone().is_empty())
+            .map(|(index, _)| index)
+            .collect();
+        Statistics {
+            questions,
+            group_sizes,
+            groups_without_unanimous,
+        }
+    }
+
+    // Questions that the most groups answered unanimously.
+    pub fn most_agreed(&self) -> Vec<&str> {
+        let max = self.questions.iter().map(|q| q.unanimous).max();
+        self.agreed(max)
+    }
+
+    pub fn least_agreed(&self) -> Vec<&str> {
+        let min = self.questions.iter().map(|q| q.unanimous).min();
+        self.agreed(min)
+    }
+
+    fn agreed(&self, unanimous: Option<usize>) -> Vec<&str> {
+        self.questions
+            .iter()
+            .filter(|q| Some(q.unanimous) == unanimous)
+            .map(|q| q.question.as_str())
+            .collect()
+    }
+
+    pub fn table(&self) -> String {
+        let mut output = String::from("question groups people unanimous\n");
+        for q in &self.questions {
+            output.push_str(&format!(
+                "{:<8} {:>6} {:>6} {:>9}\n",
+                q.question, q.groups, q.people, q.unanimous
+            ));
+        }
+        output.push_str("\ngroup size groups\n");
+        for (size, count) in &self.group_sizes {
+            output.push_str(&format!("{:>10} {:>6}\n", size, count));
+        }
+        output.push_str(&format!(
+            "\nmost agreed: {}\nleast agreed: {}\nno unanimous answer: {}\n",
+            join(self.most_agreed(), ", "),
+            join(self.least_agreed(), ", "),
+            join(&self.groups_without_unanimous, ", "),
+        ));
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        let questions: Vec<_> = self
+            .questions
+            .iter()
+            .map(|q| {
+                format!(
+                    "{{\"question\":{},\"groups\":{},\"people\":{},\"unanimous\":{}}}",
+                    json_string(&q.question),
+                    q.groups,
+                    q.people,
+                    q.unanimous
+                )
+            })
+            .collect();
+        let group_sizes: Vec<_> = self
+            .group_sizes
+            .iter()
+            .map(|(size, count)| format!("\"{}\":{}", size, count))
+            .collect();
+        let quoted = |questions: Vec<&str>| join(questions.into_iter().map(json_string), ",");
+        format!(
+            "{{\"questions\":[{}],\"group_sizes\":{{{}}},\"most_agreed\":[{}],\"least_agreed\":[{}],\"groups_without_unanimous\":[{}]}}",
+            questions.join(","),
+            group_sizes.join(","),
+            quoted(self.most_agreed()),
+            quoted(self.least_agreed()),
+            join(&self.groups_without_unanimous, ","),
+        )
+    }
+}
+
+fn join<T: ToString>(items: impl IntoIterator<Item = T>, separator: &str) -> String {
+    let items: Vec<_> = items.into_iter().map(|item| item.to_string()).collect();
+    items.join(separator)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -168,4 +526,127 @@ mod tests {
     fn test_part2() {
         assert_eq!(3447, part2())
     }
+
+    #[test]
+    fn test_group_quorum() {
+        let groups = Form::letters()
+            .parse_groups("abc\n\na\nb\nc\n\nab\nac\n\na\na\na\na\n\nb\n")
+            .expect("parse");
+        let anyone: Vec<_> = groups.iter().map(|g| g.anyone().len()).collect();
+        let everyone: Vec<_> = groups.iter().map(|g| g.everyone().len()).collect();
+        assert_eq!(anyone, vec![3, 3, 3, 1, 1]);
+        assert_eq!(everyone, vec![3, 0, 1, 1, 1]);
+
+        let letters = Form::letters();
+        let person = |text| letters.parse_person(1, text).expect("parse");
+        let group = Group::new(["abc", "abd", "ae"].iter().map(|p| person(p)));
+        assert_eq!(group.at_least(2), person("ab"));
+        assert_eq!(group.at_least(4), Answers::default());
+        assert_eq!(
+            letters.parse_person(1, "aB"),
+            Err(FormError::InvalidAnswer {
+                line: 1,
+                column: 2,
+                found: 'B'
+            })
+        );
+
+        let mut answers = Answers::default();
+        assert!(answers.insert(Answers::CAPACITY - 1));
+        assert!(!answers.insert(Answers::CAPACITY));
+        assert_eq!(answers.len(), 1);
+    }
+
+    #[test]
+    fn test_statistics() {
+        let form = Form::letters();
+        let groups = form
+            .parse_groups("abc\n\na\nb\nc\n\nab\nac\n\na\na\na\na\n\nb\n")
+            .expect("parse");
+        let stats = Statistics::new(&form, &groups);
+        assert_eq!(
+            stats.questions[0],
+            QuestionStats {
+                question: "a".to_string(),
+                groups: 4,
+                people: 8,
+                unanimous: 3
+            }
+        );
+        assert_eq!(stats.group_sizes.get(&1), Some(&2));
+        assert_eq!(stats.group_sizes.get(&4), Some(&1));
+        assert_eq!(stats.groups_without_unanimous, vec![1]);
+        assert_eq!(stats.most_agreed(), vec!["a"]);
+        assert_eq!(stats.least_agreed().len(), 23);
+        assert!(stats.table().contains("a             4      8         3\n"));
+        assert!(stats.to_json().starts_with(
+            "{\"questions\":[{\"question\":\"a\",\"groups\":4,\"people\":8,\"unanimous\":3},"
+        ));
+        assert!(stats
+            .to_json()
+            .ends_with("\"groups_without_unanimous\":[1]}"));
+    }
+
+    #[test]
+    fn test_form_alphabets() {
+        let form = Form::new(vec!["q1", "q10", "é", "ß"]).expect("form");
+        let groups = form
+            .parse_groups("q10 q1é\r\nq1\tß\r\n \r\nq10\r\n")
+            .expect("parse");
+        assert_eq!(groups.len(), 2);
+        assert_eq!(groups[0].size(), 2);
+        assert_eq!(groups[0].everyone().len(), 1);
+        assert_eq!(groups[0].anyone().len(), 4);
+        assert!(groups[1].everyone().contains(1));
+        assert_eq!(
+            form.parse_groups("q1\nq1x"),
+            Err(FormError::InvalidAnswer {
+                line: 2,
+                column: 3,
+                found: 'x'
+            })
+        );
+        let form = Form::new(vec!["é", "ß"]).expect("form");
+        assert_eq!(form.parse_person(1, "ß é").map(|a| a.len()), Ok(2));
+        assert_eq!(
+            form.parse_person(1, "ßéx"),
+            Err(FormError::InvalidAnswer {
+                line: 1,
+                column: 3,
+                found: 'x'
+            })
+        );
+        let form = Form::new(vec!["ab", "abc", "cd"]).expect("form");
+        let answers = form.parse_person(1, "abcd").expect("parse");
+        assert!(answers.contains(0) && answers.contains(2) && !answers.contains(1));
+        assert!(form.parse_person(1, "abc").expect("parse").contains(1));
+        assert_eq!(
+            form.parse_person(1, "abcx"),
+            Err(FormError::InvalidAnswer {
+                line: 1,
+                column: 4,
+                found: 'x'
+            })
+        );
+        assert_eq!(
+            Form::letters()
+                .parse_groups("ab\r\n\r\nc\r\n")
+                .expect("parse")[0]
+                .anyone()
+                .len(),
+            2
+        );
+        assert_eq!(
+            Form::new(vec!["a", "a"]),
+            Err(FormError::DuplicateQuestion("a".to_string()))
+        );
+        assert_eq!(
+            Form::new(vec!["a b"]),
+            Err(FormError::InvalidQuestion("a b".to_string()))
+        );
+        assert_eq!(
+            Form::new((0..129).map(|i| i.to_string())),
+            Err(FormError::TooManyQuestions(129))
+        );
+    }
 }
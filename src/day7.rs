@@ -94,115 +94,822 @@
 // How many individual bags are required inside your single shiny gold
 // bag?
 
-use petgraph::{algo::all_simple_paths, prelude::DiGraph, Direction};
+use crate::json::json_string;
+use petgraph::{
+    algo::{tarjan_scc, toposort},
+    dot::Dot,
+    prelude::{DiGraph, NodeIndex},
+    visit::EdgeRef,
+    Direction,
+};
 
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::path::Path;
 
+#[derive(Debug, PartialEq)]
+pub enum BagError {
+    Syntax {
+        line: usize,
+        column: usize,
+        expected: String,
+    },
+    UnknownColour(String),
+    Overflow(String),
+    DuplicateRule(String),
+    ZeroQuantity {
+        outer: String,
+        inner: String,
+    },
+    UndefinedColour {
+        colour: String,
+        referenced_by: String,
+    },
+    Cycle(Vec<String>),
+}
+
+// Which part of the rule graph to export: everything, a colour and
+// what it holds, or a colour and what can hold it.
+#[derive(Clone, Copy, Debug)]
+pub enum Scope<'a> {
+    All,
+    Inside(&'a str),
+    Containing(&'a str),
+}
+
+// One nesting of an inner colour inside an outer one, with the
+// number of inner bags it accounts for.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Chain {
+    pub colours: Vec<String>,
+    pub quantity: u64,
+}
+
+impl std::fmt::Display for Chain {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (x{})", self.colours.join(" -> "), self.quantity)
+    }
+}
+
+// A change to how many `inner` bags a `colour` bag holds; `None` on
+// one side means the entry was added or removed.
+#[derive(Debug, PartialEq)]
+pub struct ContentChange {
+    pub colour: String,
+    pub inner: String,
+    pub before: Option<u32>,
+    pub after: Option<u32>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RuleDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+    pub changed: Vec<ContentChange>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Impact {
+    pub gained_containers: BTreeSet<String>,
+    pub lost_containers: BTreeSet<String>,
+    pub count_before: Option<u64>,
+    pub count_after: Option<u64>,
+}
+
+// `quantity` bags of `colour` inside the parent (or carried, at the
+// root), each holding `children`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NestingTree {
+    pub colour: String,
+    pub quantity: u64,
+    pub children: Vec<NestingTree>,
+}
+
+impl NestingTree {
+    fn write(&self, f: &mut std::fmt::Formatter<'_>, depth: usize) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:indent$}{} {}",
+            "",
+            self.quantity,
+            self.colour,
+            indent = depth * 2
+        )?;
+        for child in &self.children {
+            child.write(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+// The outer bags to carry, one tree per colour with the number of
+// bags of that colour at its root.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Plan {
+    pub total_bags: u64,
+    pub bags: Vec<NestingTree>,
+}
+
+// The total, then one line per bag, indented by depth and giving the
+// quantity carried or inside its parent.
+impl std::fmt::Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} bags in total", self.total_bags)?;
+        for tree in &self.bags {
+            tree.write(f, 0)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    pub colour: String,
+    pub contents: Vec<(u32, String)>,
+}
+
+// The canonical form of a rule, e.g.
+// "light red bags contain 1 bright white bag, 2 muted yellow bags."
+impl std::fmt::Display for Rule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} bags contain ", self.colour)?;
+        if self.contents.is_empty() {
+            write!(f, "no other bags")?;
+        }
+        for (index, (count, colour)) in self.contents.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            let bags = if *count == 1 { "bag" } else { "bags" };
+            write!(f, "{} {} {}", count, colour, bags)?;
+        }
+        write!(f, ".")
+    }
+}
+
+// The containment graph, built once: an edge from a colour to each
+// colour it must directly contain, weighted by quantity. Colours are
+// indexed in order of first appearance, so the mapping is stable
+// across runs.
+#[derive(Debug)]
+pub struct BagRules {
+    graph: DiGraph<String, u32>,
+    indices: HashMap<String, NodeIndex>,
+    // Each rule's colour, in the order the rules were given.
+    defined: Vec<NodeIndex>,
+}
+
+impl BagRules {
+    // Rules are validated up front, so every query can assume each
+    // colour is defined exactly once and the graph is acyclic.
+    pub fn new(rules: impl IntoIterator<Item = Rule>) -> Result<BagRules, Vec<BagError>> {
+        let rules: Vec<Rule> = rules.into_iter().collect();
+        let mut errors = Vec::new();
+        let mut defined = HashSet::new();
+        for rule in &rules {
+            if !defined.insert(rule.colour.as_str()) {
+                errors.push(BagError::DuplicateRule(rule.colour.clone()));
+            }
+        }
+        for rule in &rules {
+            for (quantity, colour) in &rule.contents {
+                if *quantity == 0 {
+                    errors.push(BagError::ZeroQuantity {
+                        outer: rule.colour.clone(),
+                        inner: colour.clone(),
+                    });
+                }
+                if !defined.contains(colour.as_str()) {
+                    errors.push(BagError::UndefinedColour {
+                        colour: colour.clone(),
+                        referenced_by: rule.colour.clone(),
+                    });
+                }
+            }
+        }
+        let mut bag_rules = BagRules {
+            graph: DiGraph::new(),
+            indices: HashMap::new(),
+            defined: Vec::new(),
+        };
+        for rule in &rules {
+            let outer = bag_rules.add_colour(&rule.colour);
+            bag_rules.defined.push(outer);
+            for (quantity, colour) in &rule.contents {
+                let inner = bag_rules.add_colour(colour);
+                bag_rules.graph.add_edge(outer, inner, *quantity);
+            }
+        }
+        errors.extend(bag_rules.cycles().into_iter().map(BagError::Cycle));
+        if errors.is_empty() {
+            Ok(bag_rules)
+        } else {
+            Err(errors)
+        }
+    }
+
+    // Colours on each cycle, alphabetically within a cycle.
+    fn cycles(&self) -> Vec<Vec<String>> {
+        let mut cycles: Vec<Vec<String>> = tarjan_scc(&self.graph)
+            .into_iter()
+            .filter(|component| {
+                component.len() > 1 || self.graph.contains_edge(component[0], component[0])
+            })
+            .map(|component| {
+                let mut colours: Vec<_> = component
+                    .into_iter()
+                    .map(|index| self.colour(index).to_string())
+                    .collect();
+                colours.sort();
+                colours
+            })
+            .collect();
+        cycles.sort();
+        cycles
+    }
+
+    fn add_colour(&mut self, colour: &str) -> NodeIndex {
+        if let Some(index) = self.indices.get(colour) {
+            return *index;
+        }
+        let index = self.graph.add_node(colour.to_string());
+        self.indices.insert(colour.to_string(), index);
+        index
+    }
+
+    pub fn index(&self, colour: &str) -> Option<NodeIndex> {
+        self.indices.get(colour).copied()
+    }
+
+    fn try_index(&self, colour: &str) -> Result<NodeIndex, BagError> {
+        self.index(colour)
+            .ok_or_else(|| BagError::UnknownColour(colour.to_string()))
+    }
+
+    // Every colour that can eventually contain `colour`: the colours
+    // reachable from it along reversed containment edges.
+    pub fn containers(&self, colour: &str) -> Result<BTreeSet<&str>, BagError> {
+        let start = self.try_index(colour)?;
+        let seen = self.reachable(start, Direction::Incoming);
+        Ok(seen.into_iter().map(|index| self.colour(index)).collect())
+    }
+
+    fn reachable(&self, start: NodeIndex, direction: Direction) -> HashSet<NodeIndex> {
+        let mut seen = HashSet::new();
+        let mut pending = vec![start];
+        while let Some(index) = pending.pop() {
+            for next in self.graph.neighbors_directed(index, direction) {
+                if seen.insert(next) {
+                    pending.push(next);
+                }
+            }
+        }
+        seen
+    }
+
+    pub fn subgraph(&self, scope: Scope) -> Result<DiGraph<String, u32>, BagError> {
+        let (colour, direction) = match scope {
+            Scope::All => return Ok(self.graph.clone()),
+            Scope::Inside(colour) => (colour, Direction::Outgoing),
+            Scope::Containing(colour) => (colour, Direction::Incoming),
+        };
+        let start = self.try_index(colour)?;
+        let mut keep = self.reachable(start, direction);
+        keep.insert(start);
+        Ok(self.graph.filter_map(
+            |index, colour| keep.get(&index).map(|_| colour.clone()),
+            |_, quantity| Some(*quantity),
+        ))
+    }
+
+    // Graphviz DOT with colours as node labels and quantities on edges.
+    pub fn to_dot(&self, scope: Scope) -> Result<String, BagError> {
+        Ok(Dot::new(&self.subgraph(scope)?).to_string())
+    }
+
+    // An object mapping each colour to the colours it directly
+    // contains, e.g. {"bright white":[{"colour":"shiny gold","count":1}]}.
+    pub fn to_json(&self, scope: Scope) -> Result<String, BagError> {
+        let graph = self.subgraph(scope)?;
+        let colours: Vec<_> = graph
+            .node_indices()
+            .map(|index| {
+                let mut contents: Vec<_> = graph
+                    .edges(index)
+                    .map(|edge| {
+                        format!(
+                            "{{\"colour\":{},\"count\":{}}}",
+                            json_string(&graph[edge.target()]),
+                            edge.weight()
+                        )
+                    })
+                    .collect();
+                // petgraph lists a node's edges newest first.
+                contents.reverse();
+                format!("{}:[{}]", json_string(&graph[index]), contents.join(","))
+            })
+            .collect();
+        Ok(format!("{{{}}}", colours.join(",")))
+    }
+
+    // The colours `index` directly contains, in rule order.
+    fn contents(&self, index: NodeIndex) -> Vec<(NodeIndex, u32)> {
+        let mut contents: Vec<_> = self
+            .graph
+            .edges(index)
+            .map(|edge| (edge.target(), *edge.weight()))
+            .collect();
+        contents.reverse();
+        contents
+    }
+
+    // Every way `inner` is nested inside `outer`, in rule order.
+    pub fn chains(&self, outer: &str, inner: &str) -> Result<Vec<Chain>, BagError> {
+        let start = self.try_index(outer)?;
+        let end = self.try_index(inner)?;
+        let mut leads_to_end = self.reachable(end, Direction::Incoming);
+        leads_to_end.insert(end);
+        let mut chains = Vec::new();
+        let mut path = vec![start];
+        self.extend_chains(&mut path, 1, end, &leads_to_end, &mut chains)?;
+        Ok(chains)
+    }
+
+    fn extend_chains(
+        &self,
+        path: &mut Vec<NodeIndex>,
+        quantity: u64,
+        end: NodeIndex,
+        leads_to_end: &HashSet<NodeIndex>,
+        chains: &mut Vec<Chain>,
+    ) -> Result<(), BagError> {
+        let last = *path.last().expect("path");
+        for (next, weight) in self.contents(last) {
+            if !leads_to_end.contains(&next) {
+                continue;
+            }
+            let quantity = quantity
+                .checked_mul(u64::from(weight))
+                .ok_or_else(|| BagError::Overflow(self.colour(path[0]).to_string()))?;
+            path.push(next);
+            if next == end {
+                chains.push(Chain {
+                    colours: path
+                        .iter()
+                        .map(|index| self.colour(*index).to_string())
+                        .collect(),
+                    quantity,
+                });
+            } else {
+                self.extend_chains(path, quantity, end, leads_to_end, chains)?;
+            }
+            path.pop();
+        }
+        Ok(())
+    }
+
+    pub fn shortest_chain(&self, outer: &str, inner: &str) -> Result<Option<Chain>, BagError> {
+        let chains = self.chains(outer, inner)?;
+        Ok(chains.into_iter().min_by_key(|chain| chain.colours.len()))
+    }
+
+    // The chain contributing the most `inner` bags.
+    pub fn richest_chain(&self, outer: &str, inner: &str) -> Result<Option<Chain>, BagError> {
+        let chains = self.chains(outer, inner)?;
+        Ok(chains
+            .into_iter()
+            .min_by_key(|chain| std::cmp::Reverse(chain.quantity)))
+    }
+
+    // The cheapest way to carry the `required` bags, counting every
+    // bag carried, nested or not. An outer bag that isn't required, or
+    // one more of a colour than required, can always be unpacked for a
+    // cheaper plan, so the best plan carries only required colours,
+    // each exactly as many as the outer bags around it don't already
+    // supply. Settling colours from the outermost inwards finds it.
+    pub fn plan(&self, required: &[(u64, &str)]) -> Result<Plan, BagError> {
+        let mut needed: Vec<(NodeIndex, u64)> = Vec::new();
+        for (count, colour) in required {
+            let index = self.try_index(colour)?;
+            match needed.iter_mut().find(|(needed, _)| *needed == index) {
+                Some((_, total)) => {
+                    *total = total
+                        .checked_add(*count)
+                        .ok_or_else(|| BagError::Overflow(colour.to_string()))?
+                }
+                None => needed.push((index, *count)),
+            }
+        }
+        let order: HashMap<NodeIndex, usize> = toposort(&self.graph, None)
+            .expect("acyclic")
+            .into_iter()
+            .enumerate()
+            .map(|(position, index)| (index, position))
+            .collect();
+        let mut outermost_first: Vec<_> = (0..needed.len()).collect();
+        outermost_first.sort_by_key(|i| order[&needed[*i].0]);
+
+        let mut memo = HashMap::new();
+        let mut supplied: HashMap<NodeIndex, u64> = HashMap::new();
+        let mut carried = vec![0; needed.len()];
+        let mut total_bags = 0u64;
+        for i in outermost_first {
+            let (outer, count) = needed[i];
+            let overflow = || BagError::Overflow(self.colour(outer).to_string());
+            let quantity = count.saturating_sub(supplied.get(&outer).copied().unwrap_or(0));
+            if quantity == 0 {
+                continue;
+            }
+            carried[i] = quantity;
+            let held = self.totals_from(outer, &mut memo)?;
+            let bags = held
+                .values()
+                .try_fold(1u64, |sum, count| sum.checked_add(*count))
+                .and_then(|per_bag| per_bag.checked_mul(quantity))
+                .and_then(|bags| bags.checked_add(total_bags))
+                .ok_or_else(overflow)?;
+            total_bags = bags;
+            for (inner, count) in held {
+                let supply = supplied.entry(inner).or_insert(0);
+                *supply = count
+                    .checked_mul(quantity)
+                    .and_then(|bags| bags.checked_add(*supply))
+                    .ok_or_else(overflow)?;
+            }
+        }
+        let bags = needed
+            .iter()
+            .zip(carried)
+            .filter(|(_, quantity)| *quantity > 0)
+            .map(|((outer, _), quantity)| self.nesting_tree(*outer, quantity))
+            .collect();
+        Ok(Plan { total_bags, bags })
+    }
+
+    // How many bags of each colour a single bag at `index` holds.
+    fn totals_from(
+        &self,
+        index: NodeIndex,
+        memo: &mut HashMap<NodeIndex, HashMap<NodeIndex, u64>>,
+    ) -> Result<HashMap<NodeIndex, u64>, BagError> {
+        if let Some(totals) = memo.get(&index) {
+            return Ok(totals.clone());
+        }
+        let mut totals = HashMap::new();
+        for (inner, weight) in self.contents(index) {
+            let inner_totals = self.totals_from(inner, memo)?;
+            let direct = std::iter::once((inner, 1));
+            for (colour, count) in direct.chain(inner_totals) {
+                let total = totals.entry(colour).or_insert(0u64);
+                *total = count
+                    .checked_mul(u64::from(weight))
+                    .and_then(|bags| bags.checked_add(*total))
+                    .ok_or_else(|| BagError::Overflow(self.colour(index).to_string()))?;
+            }
+        }
+        memo.insert(index, totals.clone());
+        Ok(totals)
+    }
+
+    fn nesting_tree(&self, index: NodeIndex, quantity: u64) -> NestingTree {
+        NestingTree {
+            colour: self.colour(index).to_string(),
+            quantity,
+            children: self
+                .contents(index)
+                .into_iter()
+                .map(|(inner, count)| self.nesting_tree(inner, u64::from(count)))
+                .collect(),
+        }
+    }
+
+    // The total number of bags a single `colour` bag must hold.
+    pub fn count_inside(&self, colour: &str) -> Result<u64, BagError> {
+        let start = self.try_index(colour)?;
+        self.count_from(start, &mut HashMap::new())
+    }
+
+    fn count_from(
+        &self,
+        index: NodeIndex,
+        memo: &mut HashMap<NodeIndex, u64>,
+    ) -> Result<u64, BagError> {
+        if let Some(count) = memo.get(&index) {
+            return Ok(*count);
+        }
+        let mut count = 0u64;
+        for edge in self.graph.edges(index) {
+            let inner = self.count_from(edge.target(), memo)?;
+            count = inner
+                .checked_add(1)
+                .and_then(|bags| bags.checked_mul(u64::from(*edge.weight())))
+                .and_then(|bags| bags.checked_add(count))
+                .ok_or_else(|| BagError::Overflow(self.colour(index).to_string()))?;
+        }
+        memo.insert(index, count);
+        Ok(count)
+    }
+
+    // How many bags of each colour that holds nothing a single
+    // `colour` bag ultimately contains.
+    pub fn leaf_breakdown(&self, colour: &str) -> Result<BTreeMap<&str, u64>, BagError> {
+        let start = self.try_index(colour)?;
+        let leaves = self.leaves_from(start, &mut HashMap::new())?;
+        Ok(leaves
+            .into_iter()
+            .map(|(index, count)| (self.colour(index), count))
+            .collect())
+    }
+
+    fn leaves_from(
+        &self,
+        index: NodeIndex,
+        memo: &mut HashMap<NodeIndex, BTreeMap<NodeIndex, u64>>,
+    ) -> Result<BTreeMap<NodeIndex, u64>, BagError> {
+        if let Some(leaves) = memo.get(&index) {
+            return Ok(leaves.clone());
+        }
+        let mut leaves = BTreeMap::new();
+        for edge in self.graph.edges(index) {
+            let inner = edge.target();
+            let inner_leaves = if self.graph.edges(inner).next().is_none() {
+                vec![(inner, 1)].into_iter().collect()
+            } else {
+                self.leaves_from(inner, memo)?
+            };
+            for (leaf, count) in inner_leaves {
+                let total = leaves.entry(leaf).or_insert(0u64);
+                *total = count
+                    .checked_mul(u64::from(*edge.weight()))
+                    .and_then(|bags| bags.checked_add(*total))
+                    .ok_or_else(|| BagError::Overflow(self.colour(index).to_string()))?;
+            }
+        }
+        memo.insert(index, leaves.clone());
+        Ok(leaves)
+    }
+
+    pub fn colour(&self, index: NodeIndex) -> &str {
+        &self.graph[index]
+    }
+
+    pub fn colours(&self) -> impl Iterator<Item = &str> {
+        self.graph
+            .node_indices()
+            .map(move |index| self.colour(index))
+    }
+
+    pub fn graph(&self) -> &DiGraph<String, u32> {
+        &self.graph
+    }
+
+    // One rule per colour, in the order the rules were given.
+    pub fn rules(&self) -> Vec<Rule> {
+        self.defined
+            .iter()
+            .map(|&index| Rule {
+                colour: self.colour(index).to_string(),
+                contents: self
+                    .contents(index)
+                    .into_iter()
+                    .map(|(inner, count)| (count, self.colour(inner).to_string()))
+                    .collect(),
+            })
+            .collect()
+    }
+
+    fn contents_by_colour(&self, index: NodeIndex) -> BTreeMap<&str, u32> {
+        self.contents(index)
+            .into_iter()
+            .map(|(inner, count)| (self.colour(inner), count))
+            .collect()
+    }
+
+    // What changed going from these rules to `new`, with changed
+    // contents listed in rule order.
+    pub fn diff(&self, new: &BagRules) -> RuleDiff {
+        let added = new
+            .colours()
+            .filter(|colour| self.index(colour).is_none())
+            .map(str::to_string)
+            .collect();
+        let removed = self
+            .colours()
+            .filter(|colour| new.index(colour).is_none())
+            .map(str::to_string)
+            .collect();
+        let mut changed = Vec::new();
+        for &index in &self.defined {
+            let colour = self.colour(index);
+            let new_index = match new.index(colour) {
+                Some(new_index) => new_index,
+                None => continue,
+            };
+            let before = self.contents_by_colour(index);
+            let after = new.contents_by_colour(new_index);
+            let inners: BTreeSet<_> = before.keys().chain(after.keys()).collect();
+            for inner in inners {
+                let (before, after) = (before.get(inner).copied(), after.get(inner).copied());
+                if before != after {
+                    changed.push(ContentChange {
+                        colour: colour.to_string(),
+                        inner: inner.to_string(),
+                        before,
+                        after,
+                    });
+                }
+            }
+        }
+        RuleDiff {
+            added,
+            removed,
+            changed,
+        }
+    }
+
+    // How the answers for `target` differ between these rules and
+    // `new`; a target missing from one side has no containers and no
+    // count there.
+    pub fn impact(&self, new: &BagRules, target: &str) -> Result<Impact, BagError> {
+        let containers = |rules: &BagRules| -> BTreeSet<String> {
+            rules
+                .containers(target)
+                .map(|colours| colours.into_iter().map(str::to_string).collect())
+                .unwrap_or_default()
+        };
+        let count = |rules: &BagRules| match rules.index(target) {
+            Some(_) => rules.count_inside(target).map(Some),
+            None => Ok(None),
+        };
+        let (before, after) = (containers(self), containers(new));
+        Ok(Impact {
+            gained_containers: after.difference(&before).cloned().collect(),
+            lost_containers: before.difference(&after).cloned().collect(),
+            count_before: count(self)?,
+            count_after: count(new)?,
+        })
+    }
+}
+
+impl std::str::FromStr for BagRules {
+    type Err = Vec<BagError>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BagRules::new(parse_rules(s)?)
+    }
+}
+
 pub fn part1() -> u32 {
     let path = Path::new("day7-input.txt");
     let input = std::fs::read_to_string(path).expect("read");
-    let rules = input.as_str().lines();
-    let mut all_nodes = HashSet::new();
-    let mut all_edges = Vec::new();
-    for (node, mut edges) in rules.map(parse_rule) {
-        all_nodes.insert(node);
-        all_edges.append(&mut edges);
-    }
-    let mut nodeids = HashMap::new();
-    for (index, node) in all_nodes.iter().enumerate() {
-        nodeids.insert(node.to_string(), index as u32);
-    }
-    let graph = DiGraph::<u32, u32, _>::from_edges(
-        all_edges
-            .iter()
-            .map(|(a, s, w)| (nodeid(&nodeids, a), nodeid(&nodeids, s), w)),
-    );
-    count_to(graph, nodeid(&nodeids, "shiny gold"))
+    let rules: BagRules = input.parse().expect("parse");
+    rules.containers("shiny gold").expect("shiny gold").len() as u32
 }
 
-pub fn part2() -> u32 {
+pub fn part2() -> u64 {
     let path = Path::new("day7-input.txt");
     let input = std::fs::read_to_string(path).expect("read");
-    let rules = input.as_str().lines();
-    let mut all_nodes = HashSet::new();
-    let mut all_edges = Vec::new();
-    for (node, mut edges) in rules.map(parse_rule) {
-        all_nodes.insert(node);
-        all_edges.append(&mut edges);
-    }
-    let mut nodeids = HashMap::new();
-    for (index, node) in all_nodes.iter().enumerate() {
-        nodeids.insert(node.to_string(), index as u32);
-    }
-    let graph = DiGraph::<u32, u32>::from_edges(
-        all_edges
-            .iter()
-            .map(|(a, s, w)| (nodeid(&nodeids, a), nodeid(&nodeids, s), w)),
-    );
-    sum_from(&graph, nodeid(&nodeids, "shiny gold").into(), 1) - 1
-}
-
-fn parse_rule(rule: &str) -> (String, Vec<(String, String, u32)>) {
-    let a_rest: Vec<_> = rule.split(" bags contain ").collect();
-    let (a, rest) = (a_rest[0], a_rest[1]);
-    let w_ds: Vec<(u32, String)> = match rest {
-        "no other bags." => vec![],
-        other => other
-            .split(',')
-            .map(|w_d| {
-                let w_d1_d2_rest: Vec<_> = w_d.split_whitespace().collect();
-                let (w, d1, d2, _) = (
-                    w_d1_d2_rest[0],
-                    w_d1_d2_rest[1],
-                    w_d1_d2_rest[2],
-                    w_d1_d2_rest[3],
-                );
-                let mut d = d1.to_string();
-                d.push_str(" ");
-                d.push_str(d2);
-                (w.parse().expect("u32-parse"), d)
-            })
-            .collect(),
-    };
-    (
-        a.to_string(),
-        w_ds.into_iter()
-            .map(|(w, d)| (a.to_string(), d, w))
-            .collect(),
-    )
+    let rules: BagRules = input.parse().expect("parse");
+    rules.count_inside("shiny gold").expect("shiny gold")
 }
 
-fn count_to(graph: DiGraph<u32, u32>, dindex: u32) -> u32 {
-    let mut nodes = HashSet::new();
-    for sindex in graph.externals(Direction::Incoming) {
-        let paths = all_simple_paths::<Vec<_>, _>(&graph, sindex, dindex.into(), 0, None);
-        for path in paths {
-            for node in path {
-                nodes.insert(node);
-            }
+pub fn parse_rules(input: &str) -> Result<Vec<Rule>, Vec<BagError>> {
+    let mut rules = Vec::new();
+    let mut errors = Vec::new();
+    for (index, text) in input.lines().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        match RuleParser::new(index + 1, text).rule() {
+            Ok(rule) => rules.push(rule),
+            Err(e) => errors.push(e),
         }
     }
-    let count = nodes.len();
-    if count > 0 {
-        (count - 1) as u32
+    if errors.is_empty() {
+        Ok(rules)
     } else {
-        0
+        Err(errors)
     }
 }
 
-fn sum_from(graph: &DiGraph<u32, u32>, start: petgraph::prelude::NodeIndex, weight: u32) -> u32 {
-    let edges = graph.edges(start);
-    let nodes = graph.neighbors(start);
-    let mut sum = 0;
-    for (edge, node) in edges.zip(nodes) {
-        sum += sum_from(&graph, node, *edge.weight());
-    }
-    weight + weight * sum
+pub fn write_rules(rules: &[Rule]) -> String {
+    rules.iter().map(|rule| format!("{}\n", rule)).collect()
 }
 
-fn nodeid(nodeids: &HashMap<String, u32>, node: &str) -> u32 {
-    *nodeids.get(node).expect("get")
+// rule     = colour ("bag" | "bags") "contain" contents "."
+// contents = "no" "other" ("bag" | "bags") | item ("," item)*
+// item     = count colour ("bag" | "bags")
+// colour   = word+
+struct RuleParser<'a> {
+    line: usize,
+    tokens: Vec<(usize, &'a str)>,
+    position: usize,
+    end: usize,
+}
+
+impl<'a> RuleParser<'a> {
+    // Words and the punctuation marks ',' and '.', each with its
+    // 1-based column.
+    fn new(line: usize, text: &'a str) -> Self {
+        let mut tokens = Vec::new();
+        let mut word: Option<(usize, usize)> = None;
+        let mut column = 0;
+        for (offset, c) in text.char_indices() {
+            column += 1;
+            if c.is_whitespace() || c == ',' || c == '.' {
+                if let Some((start, word_column)) = word.take() {
+                    tokens.push((word_column, &text[start..offset]));
+                }
+                if !c.is_whitespace() {
+                    tokens.push((column, &text[offset..offset + 1]));
+                }
+            } else if word.is_none() {
+                word = Some((offset, column));
+            }
+        }
+        if let Some((start, word_column)) = word {
+            tokens.push((word_column, &text[start..]));
+        }
+        RuleParser {
+            line,
+            tokens,
+            position: 0,
+            end: column + 1,
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).map(|(_, token)| *token)
+    }
+
+    fn error(&self, expected: &str) -> BagError {
+        let column = self
+            .tokens
+            .get(self.position)
+            .map_or(self.end, |(column, _)| *column);
+        BagError::Syntax {
+            line: self.line,
+            column,
+            expected: expected.to_string(),
+        }
+    }
+
+    fn expect(&mut self, expected: &[&str]) -> Result<(), BagError> {
+        match self.peek() {
+            Some(token) if expected.contains(&token) => {
+                self.position += 1;
+                Ok(())
+            }
+            _ => Err(self.error(&expected.join(" or "))),
+        }
+    }
+
+    fn rule(&mut self) -> Result<Rule, BagError> {
+        let colour = self.colour()?;
+        self.expect(&["bag", "bags"])?;
+        self.expect(&["contain"])?;
+        let mut contents = Vec::new();
+        if self.peek() == Some("no") {
+            self.position += 1;
+            self.expect(&["other"])?;
+            self.expect(&["bag", "bags"])?;
+        } else {
+            loop {
+                let count = self
+                    .peek()
+                    .filter(|token| token.bytes().all(|b| b.is_ascii_digit()))
+                    .and_then(|token| token.parse().ok())
+                    .ok_or_else(|| self.error("count"))?;
+                self.position += 1;
+                contents.push((count, self.colour()?));
+                self.expect(&["bag", "bags"])?;
+                if self.peek() != Some(",") {
+                    break;
+                }
+                self.position += 1;
+            }
+        }
+        self.expect(&["."])?;
+        if self.peek().is_some() {
+            return Err(self.error("end of rule"));
+        }
+        Ok(Rule { colour, contents })
+    }
+
+    fn colour(&mut self) -> Result<String, BagError> {
+        let mut words = Vec::new();
+        while let Some(word) = self.peek() {
+            if matches!(word, "bag" | "bags" | "," | ".") {
+                break;
+            }
+            words.push(word);
+            self.position += 1;
+        }
+        if words.is_empty() {
+            Err(self.error("colour"))
+        } else {
+            Ok(words.join(" "))
+        }
+    }
 }
 
 #[cfg(test)]
@@ -218,4 +925,355 @@ mod tests {
     fn test_part2() {
         assert_eq!(34862, part2())
     }
+
+    const EXAMPLE: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.
+dark orange bags contain 3 bright white bags, 4 muted yellow bags.
+bright white bags contain 1 shiny gold bag.
+muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.
+shiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.
+dark olive bags contain 3 faded blue bags, 4 dotted black bags.
+vibrant plum bags contain 5 faded blue bags, 6 dotted black bags.
+faded blue bags contain no other bags.
+dotted black bags contain no other bags.
+";
+
+    #[test]
+    fn test_bag_rules() {
+        let rules: BagRules = EXAMPLE.parse().expect("parse");
+        let colours: Vec<_> = rules.colours().collect();
+        assert_eq!(colours[..3], ["light red", "bright white", "muted yellow"]);
+        assert_eq!(colours.len(), 9);
+        let gold = rules.index("shiny gold").expect("shiny gold");
+        assert_eq!(rules.colour(gold), "shiny gold");
+        assert_eq!(rules.index("mauve"), None);
+        assert_eq!(
+            rules.containers("shiny gold"),
+            Ok(
+                vec!["bright white", "dark orange", "light red", "muted yellow"]
+                    .into_iter()
+                    .collect()
+            )
+        );
+        assert_eq!(rules.containers("light red"), Ok(BTreeSet::new()));
+        assert_eq!(rules.containers("dark olive").map(|c| c.len()), Ok(5));
+        assert_eq!(
+            rules.containers("mauve"),
+            Err(BagError::UnknownColour("mauve".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_count_inside() {
+        let rules: BagRules = EXAMPLE.parse().expect("parse");
+        assert_eq!(rules.count_inside("shiny gold"), Ok(32));
+        assert_eq!(rules.count_inside("faded blue"), Ok(0));
+        let leaves = rules.leaf_breakdown("shiny gold").expect("shiny gold");
+        assert_eq!(leaves.get("faded blue"), Some(&13));
+        assert_eq!(leaves.get("dotted black"), Some(&16));
+        assert_eq!(leaves.len(), 2);
+
+        let deep: String = (0..70)
+            .map(|level| {
+                format!(
+                    "shade c{} bags contain 2 shade c{} bags.\n",
+                    level,
+                    level + 1
+                )
+            })
+            .chain(Some("shade c70 bags contain no other bags.".to_string()))
+            .collect();
+        let rules: BagRules = deep.parse().expect("parse");
+        assert_eq!(rules.count_inside("shade c10"), Ok((1u64 << 61) - 2));
+        assert_eq!(
+            rules.count_inside("shade c0"),
+            Err(BagError::Overflow("shade c6".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        let input = "light red bags contain 1 bright white bag, 0 muted yellow bags.
+bright white bags contain 1 shiny gold bag, 2 faded blue bags.
+shiny gold bags contain 1 light red bag.
+muted yellow bags contain 1 muted yellow bag.
+muted yellow bags contain no other bags.
+";
+        assert_eq!(
+            input.parse::<BagRules>().expect_err("invalid"),
+            vec![
+                BagError::DuplicateRule("muted yellow".to_string()),
+                BagError::ZeroQuantity {
+                    outer: "light red".to_string(),
+                    inner: "muted yellow".to_string()
+                },
+                BagError::UndefinedColour {
+                    colour: "faded blue".to_string(),
+                    referenced_by: "bright white".to_string()
+                },
+                BagError::Cycle(vec![
+                    "bright white".to_string(),
+                    "light red".to_string(),
+                    "shiny gold".to_string()
+                ]),
+                BagError::Cycle(vec!["muted yellow".to_string()]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_export() {
+        let rules: BagRules = EXAMPLE.parse().expect("parse");
+        assert_eq!(
+            rules.to_dot(Scope::Inside("vibrant plum")),
+            Ok("digraph {
+    0 [ label = \"faded blue\" ]
+    1 [ label = \"vibrant plum\" ]
+    2 [ label = \"dotted black\" ]
+    1 -> 0 [ label = \"5\" ]
+    1 -> 2 [ label = \"6\" ]
+}
+"
+            .to_string())
+        );
+        assert_eq!(
+            rules.to_json(Scope::Containing("bright white")),
+            Ok(
+                "{\"light red\":[{\"colour\":\"bright white\",\"count\":1}],\
+                \"bright white\":[],\
+                \"dark orange\":[{\"colour\":\"bright white\",\"count\":3}]}"
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            rules
+                .to_dot(Scope::All)
+                .map(|dot| dot.matches("->").count()),
+            Ok(13)
+        );
+        assert_eq!(
+            rules.to_json(Scope::Inside("mauve")),
+            Err(BagError::UnknownColour("mauve".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_chains() {
+        let rules: BagRules = EXAMPLE.parse().expect("parse");
+        let chains: Vec<_> = rules
+            .chains("light red", "faded blue")
+            .expect("chains")
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            chains,
+            vec![
+                "light red -> bright white -> shiny gold -> dark olive -> faded blue (x3)",
+                "light red -> bright white -> shiny gold -> vibrant plum -> faded blue (x10)",
+                "light red -> muted yellow -> shiny gold -> dark olive -> faded blue (x12)",
+                "light red -> muted yellow -> shiny gold -> vibrant plum -> faded blue (x40)",
+                "light red -> muted yellow -> faded blue (x18)",
+            ]
+        );
+        let shortest = rules.shortest_chain("light red", "faded blue");
+        assert_eq!(shortest.expect("chains").expect("chain").quantity, 18);
+        let richest = rules.richest_chain("light red", "faded blue");
+        assert_eq!(richest.expect("chains").expect("chain").quantity, 40);
+        assert_eq!(rules.chains("faded blue", "light red"), Ok(vec![]));
+        assert_eq!(rules.shortest_chain("faded blue", "light red"), Ok(None));
+    }
+
+    #[test]
+    fn test_rule_grammar() {
+        let rules = parse_rules(
+            "  pale  bags contain 1 very dark  olive bags,2 red bag .\r\n\
+             very dark olive bags contain no other bag.\n\
+             red bags contain 10 pale bag.",
+        )
+        .expect("parse");
+        assert_eq!(
+            rules[0],
+            Rule {
+                colour: "pale".to_string(),
+                contents: vec![(1, "very dark olive".to_string()), (2, "red".to_string())]
+            }
+        );
+        assert_eq!(
+            write_rules(&rules),
+            "pale bags contain 1 very dark olive bag, 2 red bags.\n\
+             very dark olive bags contain no other bags.\n\
+             red bags contain 10 pale bags.\n"
+        );
+        assert_eq!(
+            parse_rules("red bags contain two blue bags.\nblue bags hold no other bags.\nbags contain no other bags."),
+            Err(vec![
+                BagError::Syntax {
+                    line: 1,
+                    column: 18,
+                    expected: "count".to_string()
+                },
+                BagError::Syntax {
+                    line: 2,
+                    column: 11,
+                    expected: "contain".to_string()
+                },
+                BagError::Syntax {
+                    line: 3,
+                    column: 1,
+                    expected: "colour".to_string()
+                },
+            ])
+        );
+        assert_eq!(
+            parse_rules("red bags contain +1 blue bag."),
+            Err(vec![BagError::Syntax {
+                line: 1,
+                column: 18,
+                expected: "count".to_string()
+            }])
+        );
+        assert_eq!(
+            parse_rules("red bags contain 1 blue bag"),
+            Err(vec![BagError::Syntax {
+                line: 1,
+                column: 28,
+                expected: ".".to_string()
+            }])
+        );
+    }
+
+    #[test]
+    fn test_rules_round_trip() {
+        let rules: BagRules = EXAMPLE.parse().expect("parse");
+        let text = write_rules(&rules.rules());
+        assert_eq!(text, EXAMPLE);
+        let reparsed: BagRules = text.parse().expect("reparse");
+        assert_eq!(reparsed.rules(), rules.rules());
+    }
+
+    #[test]
+    fn test_diff() {
+        let old: BagRules = EXAMPLE.parse().expect("parse");
+        let new: BagRules = EXAMPLE
+            .replace(
+                "bright white bags contain 1 shiny gold bag.",
+                "bright white bags contain 2 faded blue bags.",
+            )
+            .replace(
+                "shiny gold bags contain 1 dark olive bag,",
+                "shiny gold bags contain 3 dark olive bags,",
+            )
+            .replace(
+                "dotted black bags contain no other bags.",
+                "dotted black bags contain 1 wavy teal bag.\nwavy teal bags contain no other bags.",
+            )
+            .replace(
+                "muted yellow bags contain 2 shiny gold bags, 9 faded blue bags.\n",
+                "",
+            )
+            .replace(
+                "dark orange bags contain 3 bright white bags, 4 muted yellow bags.",
+                "dark orange bags contain 3 bright white bags.",
+            )
+            .replace(
+                "light red bags contain 1 bright white bag, 2 muted yellow bags.",
+                "light red bags contain 1 shiny gold bag.",
+            )
+            .parse()
+            .expect("parse");
+        let diff = old.diff(&new);
+        assert_eq!(diff.added, vec!["wavy teal"]);
+        assert_eq!(diff.removed, vec!["muted yellow"]);
+        let changes: Vec<_> = diff
+            .changed
+            .iter()
+            .map(|c| (c.colour.as_str(), c.inner.as_str(), c.before, c.after))
+            .collect();
+        assert_eq!(
+            changes,
+            vec![
+                ("light red", "bright white", Some(1), None),
+                ("light red", "muted yellow", Some(2), None),
+                ("light red", "shiny gold", None, Some(1)),
+                ("dark orange", "muted yellow", Some(4), None),
+                ("bright white", "faded blue", None, Some(2)),
+                ("bright white", "shiny gold", Some(1), None),
+                ("shiny gold", "dark olive", Some(1), Some(3)),
+                ("dotted black", "wavy teal", None, Some(1)),
+            ]
+        );
+        let impact = old.impact(&new, "shiny gold").expect("impact");
+        let colours = |names: &[&str]| names.iter().map(|n| n.to_string()).collect();
+        assert_eq!(impact.gained_containers, BTreeSet::new());
+        assert_eq!(
+            impact.lost_containers,
+            colours(&["bright white", "dark orange", "muted yellow"])
+        );
+        assert_eq!(impact.count_before, Some(32));
+        assert_eq!(impact.count_after, Some(3 + 3 * 11 + 2 + 2 * 17));
+        let impact = old.impact(&new, "wavy teal").expect("impact");
+        assert_eq!(impact.count_before, None);
+        assert_eq!(impact.gained_containers.len(), 5);
+    }
+
+    #[test]
+    fn test_plan() {
+        let rules: BagRules = EXAMPLE.parse().expect("parse");
+        let plan = rules
+            .plan(&[(2, "shiny gold"), (35, "faded blue")])
+            .expect("plan");
+        assert_eq!(plan.total_bags, 2 * 33 + 9);
+        assert_eq!(
+            plan.to_string(),
+            "75 bags in total
+2 shiny gold
+  1 dark olive
+    3 faded blue
+    4 dotted black
+  2 vibrant plum
+    5 faded blue
+    6 dotted black
+9 faded blue
+"
+        );
+        let plan = rules
+            .plan(&[(20, "faded blue"), (3, "shiny gold")])
+            .expect("plan");
+        assert_eq!(plan.total_bags, 99);
+        assert_eq!(plan.bags.len(), 1);
+        assert_eq!(
+            (plan.bags[0].colour.as_str(), plan.bags[0].quantity),
+            ("shiny gold", 3)
+        );
+
+        let plan = rules
+            .plan(&[(1, "light red"), (1, "dark orange")])
+            .expect("plan");
+        assert_eq!(plan.total_bags, 187 + 407);
+        let outer: Vec<_> = plan
+            .bags
+            .iter()
+            .map(|tree| (tree.colour.as_str(), tree.quantity))
+            .collect();
+        assert_eq!(outer, vec![("light red", 1), ("dark orange", 1)]);
+
+        // A light red bag already holds 5 shiny gold bags.
+        let plan = rules
+            .plan(&[(1, "light red"), (3, "shiny gold"), (3, "shiny gold")])
+            .expect("plan");
+        assert_eq!(plan.total_bags, 187 + 33);
+        assert_eq!(plan.bags[1].quantity, 1);
+
+        assert_eq!(
+            rules.plan(&[]),
+            Ok(Plan {
+                total_bags: 0,
+                bags: vec![]
+            })
+        );
+        assert_eq!(
+            rules.plan(&[(1, "mauve")]),
+            Err(BagError::UnknownColour("mauve".to_string()))
+        );
+    }
 }
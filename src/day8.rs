@@ -125,82 +125,1073 @@
 
 // Fix the program so that it terminates normally by changing exactly one jmp (to nop) or nop (to jmp). What is the value of the accumulator after the program terminates?
 
-use std::collections::HashSet;
+use petgraph::{dot::Dot, graph::DiGraph};
+use std::collections::{BTreeMap, BTreeSet, HashSet, VecDeque};
+use std::convert::TryFrom;
+use std::io::{self, BufRead, Write};
+use std::ops::{Range, RangeInclusive};
 use std::path::Path;
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    Acc(i64),
+    Jmp(i64),
+    Nop(i64),
+}
+
+impl Instruction {
+    pub fn opcode(&self) -> &'static str {
+        match self {
+            Instruction::Acc(_) => "acc",
+            Instruction::Jmp(_) => "jmp",
+            Instruction::Nop(_) => "nop",
+        }
+    }
+
+    pub fn argument(&self) -> i64 {
+        match self {
+            Instruction::Acc(arg) | Instruction::Jmp(arg) | Instruction::Nop(arg) => *arg,
+        }
+    }
+
+    // The jmp/nop swap from part two; acc has no counterpart.
+    pub fn flip(&self) -> Option<Instruction> {
+        match self {
+            Instruction::Acc(_) => None,
+            Instruction::Jmp(arg) => Some(Instruction::Nop(*arg)),
+            Instruction::Nop(arg) => Some(Instruction::Jmp(*arg)),
+        }
+    }
+}
+
+// The assembly form, e.g. "acc +7" or "jmp -20".
+impl std::fmt::Display for Instruction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {:+}", self.opcode(), self.argument())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ParseError {
+    UnknownOpcode { line: usize, opcode: String },
+    MissingArgument { line: usize },
+    InvalidArgument { line: usize, argument: String },
+    TrailingInput { line: usize, input: String },
+}
+
+fn parse_instruction(line: usize, text: &str) -> Result<Instruction, ParseError> {
+    let mut parts = text.split_whitespace();
+    let opcode = parts.next().unwrap_or("");
+    let instruction: fn(i64) -> Instruction = match opcode {
+        "acc" => Instruction::Acc,
+        "jmp" => Instruction::Jmp,
+        "nop" => Instruction::Nop,
+        _ => {
+            return Err(ParseError::UnknownOpcode {
+                line,
+                opcode: opcode.to_string(),
+            })
+        }
+    };
+    let argument = parts.next().ok_or(ParseError::MissingArgument { line })?;
+    if let Some(input) = parts.next() {
+        return Err(ParseError::TrailingInput {
+            line,
+            input: input.to_string(),
+        });
+    }
+    let arg = argument.parse().map_err(|_| ParseError::InvalidArgument {
+        line,
+        argument: argument.to_string(),
+    })?;
+    Ok(instruction(arg))
+}
+
+// Blank lines are skipped; errors carry the 1-based line number.
+pub fn parse_program(input: &str) -> Result<Vec<Instruction>, ParseError> {
+    input
+        .lines()
+        .enumerate()
+        .filter(|(_, text)| !text.trim().is_empty())
+        .map(|(index, text)| parse_instruction(index + 1, text))
+        .collect()
+}
+
+pub fn disassemble(program: &[Instruction]) -> String {
+    program
+        .iter()
+        .map(|instruction| format!("{}\n", instruction))
+        .collect()
+}
+
+// Why a machine stopped. `Loop` and `OutOfBounds` carry the repeated
+// address and the attempted jump target; `Overflow` means an acc would
+// take the accumulator out of range.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Halt {
+    Terminated,
+    Loop(usize),
+    OutOfBounds(i64),
+    Overflow,
+    StepLimit,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct TraceEntry {
+    pub step: usize,
+    pub address: usize,
+    pub instruction: Instruction,
+    pub acc_before: i64,
+    pub acc_after: i64,
+}
+
+// Every instruction a machine executed, in order.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Trace {
+    entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    pub fn entries(&self) -> &[TraceEntry] {
+        &self.entries
+    }
+
+    // How many times each address was executed.
+    pub fn profile(&self) -> BTreeMap<usize, usize> {
+        let mut profile = BTreeMap::new();
+        for entry in &self.entries {
+            *profile.entry(entry.address).or_insert(0) += 1;
+        }
+        profile
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut output = String::from("step address instruction acc\n");
+        for entry in &self.entries {
+            output.push_str(&format!(
+                "{:>4} {:>7} {:<11} {} -> {}\n",
+                entry.step,
+                entry.address,
+                entry.instruction.to_string(),
+                entry.acc_before,
+                entry.acc_after
+            ));
+        }
+        output.push_str("\naddress count\n");
+        for (address, count) in self.profile() {
+            output.push_str(&format!("{:>7} {:>5}\n", address, count));
+        }
+        output
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries: Vec<_> = self
+            .entries
+            .iter()
+            .map(|entry| {
+                format!(
+                    "{{\"step\":{},\"address\":{},\"instruction\":\"{}\",\"acc_before\":{},\"acc_after\":{}}}",
+                    entry.step, entry.address, entry.instruction, entry.acc_before, entry.acc_after
+                )
+            })
+            .collect();
+        let profile: Vec<_> = self
+            .profile()
+            .iter()
+            .map(|(address, count)| format!("\"{}\":{}", address, count))
+            .collect();
+        format!(
+            "{{\"trace\":[{}],\"profile\":{{{}}}}}",
+            entries.join(","),
+            profile.join(",")
+        )
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Machine {
+    program: Vec<Instruction>,
+    acc: i64,
+    ip: usize,
+    steps: usize,
+    // Addresses executed at least once since the pointer was last set
+    // or the program last patched.
+    visited: Vec<bool>,
+    trace: Option<Trace>,
+}
+
+impl Machine {
+    pub fn new(program: Vec<Instruction>) -> Machine {
+        Machine {
+            visited: vec![false; program.len()],
+            program,
+            acc: 0,
+            ip: 0,
+            steps: 0,
+            trace: None,
+        }
+    }
+
+    // Records every instruction executed from now on.
+    pub fn with_trace(self) -> Machine {
+        Machine {
+            trace: Some(Trace::default()),
+            ..self
+        }
+    }
+
+    pub fn trace(&self) -> Option<&Trace> {
+        self.trace.as_ref()
+    }
+
+    pub fn program(&self) -> &[Instruction] {
+        &self.program
+    }
+
+    pub fn acc(&self) -> i64 {
+        self.acc
+    }
+
+    pub fn ip(&self) -> usize {
+        self.ip
+    }
+
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+
+    pub fn set_acc(&mut self, acc: i64) {
+        self.acc = acc;
+    }
+
+    // The pointer may be anywhere in the program or just past its end.
+    // Either way the path so far no longer predicts a loop.
+    pub fn set_ip(&mut self, ip: usize) -> bool {
+        if ip > self.program.len() {
+            return false;
+        }
+        self.ip = ip;
+        self.forget_visits();
+        true
+    }
+
+    // Replaces the instruction at `address`, returning the old one.
+    pub fn patch(&mut self, address: usize, instruction: Instruction) -> Option<Instruction> {
+        let slot = self.program.get_mut(address)?;
+        let old = std::mem::replace(slot, instruction);
+        self.forget_visits();
+        Some(old)
+    }
+
+    fn forget_visits(&mut self) {
+        self.visited.iter_mut().for_each(|visited| *visited = false);
+    }
+
+    // Executes the instruction at the pointer. Returns `Terminated`
+    // once the pointer lands just past the last instruction,
+    // `OutOfBounds` for a jump anywhere else outside the program and
+    // `Overflow` for an acc the accumulator can't hold, leaving the
+    // machine unchanged in both cases.
+    pub fn step(&mut self) -> Option<Halt> {
+        let len = self.program.len();
+        if self.ip == len {
+            return Some(Halt::Terminated);
+        }
+        let acc_before = self.acc;
+        let next = match self.program[self.ip] {
+            Instruction::Jmp(offset) => {
+                let target = (self.ip as i64).saturating_add(offset);
+                if target < 0 || target as u64 > len as u64 {
+                    return Some(Halt::OutOfBounds(target));
+                }
+                target as usize
+            }
+            Instruction::Acc(amount) => match self.acc.checked_add(amount) {
+                Some(acc) => {
+                    self.acc = acc;
+                    self.ip + 1
+                }
+                None => return Some(Halt::Overflow),
+            },
+            Instruction::Nop(_) => self.ip + 1,
+        };
+        self.steps += 1;
+        self.visited[self.ip] = true;
+        if let Some(trace) = &mut self.trace {
+            trace.entries.push(TraceEntry {
+                step: self.steps,
+                address: self.ip,
+                instruction: self.program[self.ip],
+                acc_before,
+                acc_after: self.acc,
+            });
+        }
+        self.ip = next;
+        if next == len {
+            Some(Halt::Terminated)
+        } else {
+            None
+        }
+    }
+
+    // Steps until the machine halts, an instruction that has already
+    // run (in this or any earlier `step` or `run`, since the pointer
+    // was last set or the program last patched) is about to run again,
+    // or `limit` steps have run.
+    pub fn run(&mut self, limit: Option<usize>) -> Halt {
+        loop {
+            if self.visited.get(self.ip) == Some(&true) {
+                return Halt::Loop(self.ip);
+            }
+            if limit.is_some_and(|limit| self.steps >= limit) {
+                return Halt::StepLimit;
+            }
+            if let Some(halt) = self.step() {
+                return halt;
+            }
+        }
+    }
+}
+
+const HISTORY: usize = 100;
+
+// A line-oriented debugger around a `Machine`. Commands:
+//
+// break <address|opcode>   stop before that address or opcode
+// delete <address|opcode>  remove a breakpoint
+// breaks                   list breakpoints
+// step [n]                 execute n instructions (default 1)
+// continue                 run to a breakpoint, halt or loop
+// acc [value]              show or set the accumulator
+// ip [address]             show or set the instruction pointer
+// patch <address> <op> <arg>  replace an instruction
+// history [n]              the last n executed instructions
+// list                     the program, '>' at the pointer and '*'
+//                          at breakpoints
+// quit
+pub struct Debugger {
+    machine: Machine,
+    address_breaks: BTreeSet<usize>,
+    opcode_breaks: BTreeSet<&'static str>,
+    history: VecDeque<(usize, Instruction)>,
+}
+
+impl Debugger {
+    pub fn new(program: Vec<Instruction>) -> Debugger {
+        Debugger {
+            machine: Machine::new(program),
+            address_breaks: BTreeSet::new(),
+            opcode_breaks: BTreeSet::new(),
+            history: VecDeque::new(),
+        }
+    }
+
+    pub fn machine(&self) -> &Machine {
+        &self.machine
+    }
+
+    // Runs one command and returns what it prints; `None` on quit.
+    pub fn execute(&mut self, command: &str) -> Option<String> {
+        let words: Vec<_> = command.split_whitespace().collect();
+        let output = match words.as_slice() {
+            [] => String::new(),
+            ["quit"] | ["q"] => return None,
+            ["break", target] | ["b", target] => match self.breakpoint(target) {
+                Some(Breakpoint::Address(address)) => {
+                    self.address_breaks.insert(address);
+                    format!("breakpoint at {}\n", address)
+                }
+                Some(Breakpoint::Opcode(opcode)) => {
+                    self.opcode_breaks.insert(opcode);
+                    format!("breakpoint on {}\n", opcode)
+                }
+                None => format!("no such address or opcode: {}\n", target),
+            },
+            ["delete", target] | ["d", target] => {
+                let removed = match self.breakpoint(target) {
+                    Some(Breakpoint::Address(address)) => self.address_breaks.remove(&address),
+                    Some(Breakpoint::Opcode(opcode)) => self.opcode_breaks.remove(opcode),
+                    None => false,
+                };
+                if removed {
+                    format!("deleted {}\n", target)
+                } else {
+                    format!("no breakpoint at {}\n", target)
+                }
+            }
+            ["breaks"] => {
+                let addresses = self.address_breaks.iter().map(ToString::to_string);
+                let opcodes = self.opcode_breaks.iter().map(ToString::to_string);
+                let breaks: Vec<_> = addresses.chain(opcodes).collect();
+                format!("{}\n", breaks.join(" "))
+            }
+            ["step"] | ["s"] => self.step(1),
+            ["step", n] | ["s", n] => match n.parse() {
+                Ok(n) => self.step(n),
+                Err(_) => format!("not a count: {}\n", n),
+            },
+            ["continue"] | ["c"] => self.resume(),
+            ["acc"] => format!("acc {}\n", self.machine.acc()),
+            ["acc", value] => match value.parse() {
+                Ok(value) => {
+                    self.machine.set_acc(value);
+                    format!("acc {}\n", value)
+                }
+                Err(_) => format!("not a value: {}\n", value),
+            },
+            ["ip"] => format!("ip {}\n", self.machine.ip()),
+            ["ip", address] => match address.parse() {
+                Ok(address) if self.machine.set_ip(address) => self.state(),
+                _ => format!("no such address: {}\n", address),
+            },
+            ["patch", address, op, arg] => {
+                let instruction = parse_instruction(0, &format!("{} {}", op, arg));
+                match (address.parse(), instruction) {
+                    (Ok(address), Ok(instruction)) => {
+                        match self.machine.patch(address, instruction) {
+                            Some(old) => format!("{}: {} -> {}\n", address, old, instruction),
+                            None => format!("no such address: {}\n", address),
+                        }
+                    }
+                    (Err(_), _) => format!("no such address: {}\n", address),
+                    (_, Err(_)) => format!("not an instruction: {} {}\n", op, arg),
+                }
+            }
+            ["history"] => self.history(self.history.len()),
+            ["history", n] => match n.parse() {
+                Ok(n) => self.history(n),
+                Err(_) => format!("not a count: {}\n", n),
+            },
+            ["list"] | ["l"] => self.list(),
+            _ => format!("unknown command: {}\n", command.trim()),
+        };
+        Some(output)
+    }
+
+    fn breakpoint(&self, target: &str) -> Option<Breakpoint> {
+        if let Ok(address) = target.parse() {
+            return if address < self.machine.program().len() {
+                Some(Breakpoint::Address(address))
+            } else {
+                None
+            };
+        }
+        ["acc", "jmp", "nop"]
+            .iter()
+            .find(|opcode| **opcode == target)
+            .map(|opcode| Breakpoint::Opcode(opcode))
+    }
+
+    fn state(&self) -> String {
+        let ip = self.machine.ip();
+        match self.machine.program().get(ip) {
+            Some(instruction) => {
+                format!("ip {} acc {} | {}\n", ip, self.machine.acc(), instruction)
+            }
+            None => format!("ip {} acc {} | end\n", ip, self.machine.acc()),
+        }
+    }
+
+    // Executes one instruction, recording it if it ran.
+    fn execute_one(&mut self) -> Option<Halt> {
+        let ip = self.machine.ip();
+        let instruction = self.machine.program().get(ip).copied();
+        let steps = self.machine.steps();
+        let halt = self.machine.step();
+        if let Some(instruction) = instruction.filter(|_| self.machine.steps() > steps) {
+            if self.history.len() == HISTORY {
+                self.history.pop_front();
+            }
+            self.history.push_back((ip, instruction));
+        }
+        halt
+    }
+
+    fn step(&mut self, n: usize) -> String {
+        for _ in 0..n {
+            if let Some(halt) = self.execute_one() {
+                return describe(halt, &self.machine);
+            }
+        }
+        self.state()
+    }
+
+    // Runs from the current instruction, ignoring a breakpoint there,
+    // until the next breakpoint, a halt, or an instruction about to
+    // run a second time.
+    fn resume(&mut self) -> String {
+        let mut seen = HashSet::new();
+        loop {
+            let ip = self.machine.ip();
+            if !seen.insert(ip) {
+                return describe(Halt::Loop(ip), &self.machine);
+            }
+            if let Some(halt) = self.execute_one() {
+                return describe(halt, &self.machine);
+            }
+            let ip = self.machine.ip();
+            let opcode = self.machine.program()[ip].opcode();
+            if self.address_breaks.contains(&ip) || self.opcode_breaks.contains(opcode) {
+                return format!("breakpoint\n{}", self.state());
+            }
+        }
+    }
+
+    fn history(&self, n: usize) -> String {
+        self.history
+            .iter()
+            .skip(self.history.len().saturating_sub(n))
+            .map(|(address, instruction)| format!("{:>4} {}\n", address, instruction))
+            .collect()
+    }
+
+    fn list(&self) -> String {
+        self.machine
+            .program()
+            .iter()
+            .enumerate()
+            .map(|(address, instruction)| {
+                let pointer = if address == self.machine.ip() {
+                    '>'
+                } else {
+                    ' '
+                };
+                let stop = self.address_breaks.contains(&address)
+                    || self.opcode_breaks.contains(instruction.opcode());
+                let stop = if stop { '*' } else { ' ' };
+                format!("{}{}{:>4} {}\n", pointer, stop, address, instruction)
+            })
+            .collect()
+    }
+}
+
+enum Breakpoint {
+    Address(usize),
+    Opcode(&'static str),
+}
+
+fn describe(halt: Halt, machine: &Machine) -> String {
+    match halt {
+        Halt::Terminated => format!("terminated with acc {}\n", machine.acc()),
+        Halt::Loop(address) => format!("loop at {} with acc {}\n", address, machine.acc()),
+        Halt::OutOfBounds(target) => format!(
+            "jump from {} out of bounds to {} with acc {}\n",
+            machine.ip(),
+            target,
+            machine.acc()
+        ),
+        Halt::Overflow => format!("overflow at {} with acc {}\n", machine.ip(), machine.acc()),
+        Halt::StepLimit => format!("step limit with acc {}\n", machine.acc()),
+    }
+}
+
+// Reads commands from `input` until quit or end of input, writing a
+// "> " prompt before each one.
+pub fn debug<R: BufRead, W: Write>(
+    program: Vec<Instruction>,
+    input: R,
+    mut output: W,
+) -> io::Result<()> {
+    let mut debugger = Debugger::new(program);
+    write!(output, "> ")?;
+    output.flush()?;
+    for command in input.lines() {
+        match debugger.execute(&command?) {
+            Some(text) => write!(output, "{}> ", text)?,
+            None => break,
+        }
+        output.flush()?;
+    }
+    Ok(())
+}
+
 pub fn part1() -> i64 {
     let path = Path::new("day8-input.txt");
     let input = std::fs::read_to_string(path).expect("read");
-    let instructions: Vec<_> = input
-        .as_str()
-        .lines()
-        .map(|l| {
-            let parts: Vec<_> = l.split_whitespace().collect();
-            (parts[0], parts[1])
-        })
-        .enumerate()
-        .collect();
-    run(instructions).1
+    let mut machine = Machine::new(parse_program(&input).expect("parse"));
+    match machine.run(None) {
+        Halt::Loop(_) => machine.acc(),
+        halt => panic!("no loop: {:?}", halt),
+    }
 }
 
 pub fn part2() -> i64 {
     let path = Path::new("day8-input.txt");
     let input = std::fs::read_to_string(path).expect("read");
-    let instructions: Vec<_> = input
-        .as_str()
-        .lines()
-        .map(|l| {
-            let parts: Vec<_> = l.split_whitespace().collect();
-            (parts[0], parts[1])
-        })
-        .enumerate()
-        .collect();
-    for curr in 0..instructions.len() {
-        let mut changed = instructions.clone();
-        if changed[curr].1.0 == "nop" {
-            changed[curr].1.0 = "jmp";
-        } else if changed[curr].1.0 == "jmp" {
-            changed[curr].1.0 = "nop";
+    let program = parse_program(&input).expect("parse");
+    repair(&program).expect("none terminate!").acc
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Repair {
+    pub address: usize,
+    pub from: Instruction,
+    pub to: Instruction,
+    pub acc: i64,
+}
+
+// Where execution goes after `instruction` at `address`: another
+// address, or the program's length for normal termination. `None` for
+// a jump out of bounds.
+fn successor(len: usize, address: usize, instruction: Instruction) -> Option<usize> {
+    let target = match instruction {
+        Instruction::Jmp(offset) => (address as i64).checked_add(offset)?,
+        _ => address as i64 + 1,
+    };
+    if 0 <= target && target as u64 <= len as u64 {
+        Some(target as usize)
+    } else {
+        None
+    }
+}
+
+// How the accumulator moves on the way from an address to the end: the
+// total change, and the lowest and highest change after any step. An
+// i128 holds the sum of any program's worth of i64s.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Gain {
+    total: i128,
+    low: i128,
+    high: i128,
+}
+
+impl Gain {
+    fn after(self, amount: i64) -> Self {
+        let amount = i128::from(amount);
+        Gain {
+            total: amount + self.total,
+            low: 0.min(amount + self.low),
+            high: 0.max(amount + self.high),
+        }
+    }
+
+    // The final accumulator starting from `acc`, or `None` if it would
+    // overflow on the way, as `Machine::step` reports it.
+    fn end_acc(self, acc: i64) -> Option<i64> {
+        let acc = i128::from(acc);
+        let in_range = |change: i128| i64::try_from(acc + change).is_ok();
+        if in_range(self.low) && in_range(self.high) {
+            i64::try_from(acc + self.total).ok()
         } else {
-            continue;
+            None
+        }
+    }
+}
+
+// For each address (and the end, at index `len`) that reaches normal
+// termination, how the accumulator changes on the way: a reverse search
+// of the control-flow graph from the end.
+fn acc_to_end(program: &[Instruction]) -> Vec<Option<Gain>> {
+    let len = program.len();
+    let mut predecessors = vec![Vec::new(); len + 1];
+    for (address, instruction) in program.iter().enumerate() {
+        if let Some(next) = successor(len, address, *instruction) {
+            predecessors[next].push(address);
         }
-        let (terminated, global) = run(changed);
-        if terminated {
-            return global;
+    }
+    let mut to_end = vec![None; len + 1];
+    to_end[len] = Some(Gain {
+        total: 0,
+        low: 0,
+        high: 0,
+    });
+    let mut pending = vec![len];
+    while let Some(address) = pending.pop() {
+        let gain = to_end[address].expect("reaches end");
+        for previous in &predecessors[address] {
+            if to_end[*previous].is_none() {
+                let gained = match program[*previous] {
+                    Instruction::Acc(amount) => amount,
+                    _ => 0,
+                };
+                to_end[*previous] = Some(gain.after(gained));
+                pending.push(*previous);
+            }
+        }
+    }
+    to_end
+}
+
+// Whether execution from each address (and from the end, at index
+// `len`) reaches normal termination.
+fn terminating(program: &[Instruction]) -> Vec<bool> {
+    acc_to_end(program).iter().map(Option::is_some).collect()
+}
+
+// The addresses visited before the original program loops, jumps out
+// of bounds or terminates, in execution order. The accumulator is
+// ignored, so the path may run past an overflow; see `path_accs`.
+fn original_path(program: &[Instruction]) -> Vec<usize> {
+    let len = program.len();
+    let mut seen = vec![false; len];
+    let mut path = Vec::new();
+    let mut address = 0;
+    while address < len && !seen[address] {
+        seen[address] = true;
+        path.push(address);
+        match successor(len, address, program[address]) {
+            Some(next) => address = next,
+            None => break,
         }
     }
-    panic!("none terminate!");
+    path
 }
 
-fn run(instructions: Vec<(usize, (&str, &str))>) -> (bool, i64) {
-    let mut global = 0i64;
-    let mut seen = HashSet::new();
-    let mut curr = 0;
-    while curr < instructions.len() {
-        let (n, (i, v)) = instructions[curr];
-        if seen.contains(&n) {
-            return (false, global);
+// The original path with the accumulator before each address, cut
+// short after the first acc that would overflow.
+fn path_accs(program: &[Instruction]) -> Vec<(usize, i64)> {
+    let mut path = Vec::new();
+    let mut acc = Some(0i64);
+    for address in original_path(program) {
+        let before = match acc {
+            Some(before) => before,
+            None => break,
+        };
+        path.push((address, before));
+        if let Instruction::Acc(amount) = program[address] {
+            acc = before.checked_add(amount);
         }
-        seen.insert(n);
-        match i {
-            "acc" => {
-                global += v.parse::<i64>().expect("parse");
-                curr += 1;
+    }
+    path
+}
+
+// Finds the single jmp/nop flip that makes a looping program terminate,
+// in linear time. Only instructions on the original path matter, and
+// flipping one of them repairs the program exactly when its new
+// successor already reaches the end without overflowing the
+// accumulator. `None` if the program already terminates or no flip
+// works.
+pub fn repair(program: &[Instruction]) -> Option<Repair> {
+    let to_end = acc_to_end(program);
+    if to_end[0].is_some() {
+        return None;
+    }
+    path_accs(program)
+        .into_iter()
+        .find_map(|(address, acc_before)| {
+            let from = program[address];
+            let to = from.flip()?;
+            let next = successor(program.len(), address, to)?;
+            let acc = to_end[next]?.end_acc(acc_before)?;
+            Some(Repair {
+                address,
+                from,
+                to,
+                acc,
+            })
+        })
+}
+
+// Which single-instruction changes `repairs` considers. jmp/nop flips
+// are always tried; `opcodes` also allows turning any instruction into
+// any other operation with the same argument, and `arguments` allows
+// any jmp offset in the range (replacing only jmps, unless `opcodes`
+// is set too). acc and nop arguments can't affect termination, so
+// they are never varied.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mutations {
+    pub opcodes: bool,
+    pub arguments: Option<RangeInclusive<i64>>,
+}
+
+// Every single-instruction change allowed by `mutations` that makes a
+// looping program terminate, by address: the fixed changes first, then
+// jumps by increasing target. Empty if the program already terminates.
+//
+// As in `repair`, only addresses on the original path matter, and a
+// change there works exactly when its new successor reaches the end,
+// without passing back through it. So jmp offsets come straight from
+// the addresses that reach the end, and each accumulator is the one on
+// the way to the change plus the one from its successor to the end.
+// Changes that would overflow the accumulator don't terminate, so they
+// are left out.
+pub fn repairs(program: &[Instruction], mutations: &Mutations) -> Vec<Repair> {
+    let len = program.len();
+    let to_end = acc_to_end(program);
+    if to_end[0].is_some() {
+        return Vec::new();
+    }
+    let ends: Vec<usize> = (0..=len).filter(|t| to_end[*t].is_some()).collect();
+    let mut path = path_accs(program);
+    path.sort_unstable();
+
+    let mut found = Vec::new();
+    for (address, acc_before) in path {
+        let from = program[address];
+        let mut fixed = Vec::new();
+        fixed.extend(from.flip());
+        if mutations.opcodes {
+            let argument = from.argument();
+            for to in &[
+                Instruction::Acc(argument),
+                Instruction::Jmp(argument),
+                Instruction::Nop(argument),
+            ] {
+                if *to != from && !fixed.contains(to) {
+                    fixed.push(*to);
+                }
             }
-            "jmp" => {
-                let amount = v.parse::<i32>().expect("parse");
-                if amount < 0 {
-                    curr -= -amount as usize;
-                } else {
-                    curr += amount as usize;
+        }
+        let mut jumps = Vec::new();
+        let jumps_allowed = mutations.opcodes || matches!(from, Instruction::Jmp(_));
+        if let Some(arguments) = mutations.arguments.as_ref().filter(|_| jumps_allowed) {
+            let lowest = (address as i64).saturating_add(*arguments.start());
+            let highest = (address as i64).saturating_add(*arguments.end());
+            let first = ends.partition_point(|t| (*t as i64) < lowest);
+            let last = ends.partition_point(|t| (*t as i64) <= highest);
+            for target in ends.get(first..last).unwrap_or(&[]) {
+                let to = Instruction::Jmp(*target as i64 - address as i64);
+                if to != from && !fixed.contains(&to) {
+                    jumps.push(to);
                 }
             }
-            _ => {
-                curr += 1;
+        }
+        for to in fixed.into_iter().chain(jumps) {
+            let next = match successor(len, address, to) {
+                Some(next) => next,
+                None => continue,
+            };
+            let gained = match to {
+                Instruction::Acc(amount) => amount,
+                _ => 0,
+            };
+            let acc = to_end[next].and_then(|rest| rest.after(gained).end_acc(acc_before));
+            if let Some(acc) = acc {
+                found.push(Repair {
+                    address,
+                    from,
+                    to,
+                    acc,
+                });
             }
         }
     }
-    (true, global)
+    found
+}
+
+// Whether more than one change repairs the program, so the corrupted
+// instruction can't be pinned down.
+pub fn ambiguous(repairs: &[Repair]) -> bool {
+    repairs.len() > 1
+}
+
+// Whether the repairs disagree about the final accumulator, so not even
+// the answer can be pinned down.
+pub fn conflicting(repairs: &[Repair]) -> bool {
+    repairs.windows(2).any(|pair| pair[0].acc != pair[1].acc)
+}
+
+// Static analysis of a program's control flow, without running it.
+// Every instruction has exactly one successor, so the instructions
+// reachable from 0 are exactly the ones executed, and any cycle is an
+// infinite loop once entered.
+pub struct Analysis {
+    program: Vec<Instruction>,
+    blocks: Vec<Range<usize>>,
+    path: Vec<usize>,
+    reaches_end: Vec<bool>,
+}
+
+impl Analysis {
+    pub fn new(program: &[Instruction]) -> Self {
+        let len = program.len();
+        // Leaders: the entry, every jump target and whatever follows a
+        // jump.
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (address, instruction) in program.iter().enumerate() {
+            if let Instruction::Jmp(_) = instruction {
+                if let Some(target) = successor(len, address, *instruction) {
+                    leaders.insert(target);
+                }
+                leaders.insert(address + 1);
+            }
+        }
+        let leaders: Vec<_> = leaders.into_iter().filter(|&leader| leader < len).collect();
+        let blocks = leaders
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| start..leaders.get(i + 1).copied().unwrap_or(len))
+            .collect();
+        Analysis {
+            program: program.to_vec(),
+            blocks,
+            path: original_path(program),
+            reaches_end: terminating(program),
+        }
+    }
+
+    // Basic blocks as address ranges, in address order.
+    pub fn blocks(&self) -> &[Range<usize>] {
+        &self.blocks
+    }
+
+    pub fn block_of(&self, address: usize) -> Option<usize> {
+        if address >= self.program.len() {
+            return None;
+        }
+        Some(self.blocks.partition_point(|block| block.end <= address))
+    }
+
+    // The blocks as a DOT digraph, with an "exit" node for normal
+    // termination and one node per jump target outside the program.
+    // Edges are labelled "jmp" for a taken jump and "next" otherwise.
+    pub fn to_dot(&self) -> String {
+        let len = self.program.len();
+        let mut graph = DiGraph::new();
+        let nodes: Vec<_> = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let listing: String = block
+                    .clone()
+                    .map(|address| format!("{}: {}\n", address, self.program[address]))
+                    .collect();
+                graph.add_node(listing)
+            })
+            .collect();
+        let exit = graph.add_node("exit".to_string());
+        let mut outside = BTreeMap::new();
+        for (block, node) in self.blocks.iter().zip(&nodes) {
+            let last = block.end - 1;
+            let instruction = self.program[last];
+            let label = match instruction {
+                Instruction::Jmp(_) => "jmp",
+                _ => "next",
+            };
+            let target = match successor(len, last, instruction) {
+                Some(next) if next == len => exit,
+                Some(next) => nodes[self.block_of(next).expect("in program")],
+                None => {
+                    let target = (last as i64).saturating_add(instruction.argument());
+                    *outside
+                        .entry(target)
+                        .or_insert_with(|| graph.add_node(format!("out of bounds ({})", target)))
+                }
+            };
+            graph.add_edge(*node, target, label);
+        }
+        Dot::new(&graph).to_string()
+    }
+
+    // Instructions that can never run.
+    pub fn unreachable(&self) -> Vec<usize> {
+        let mut reachable = vec![false; self.program.len()];
+        for address in &self.path {
+            reachable[*address] = true;
+        }
+        (0..self.program.len())
+            .filter(|address| !reachable[*address])
+            .collect()
+    }
+
+    // How a run from 0 ends, as `Machine::run` would report it.
+    pub fn halt(&self) -> Halt {
+        let len = self.program.len();
+        let last = match self.path.last() {
+            Some(last) => *last,
+            None => return Halt::Terminated,
+        };
+        let mut acc = 0i64;
+        for address in &self.path {
+            if let Instruction::Acc(amount) = self.program[*address] {
+                acc = match acc.checked_add(amount) {
+                    Some(acc) => acc,
+                    None => return Halt::Overflow,
+                };
+            }
+        }
+        let instruction = self.program[last];
+        match successor(len, last, instruction) {
+            Some(next) if next == len => Halt::Terminated,
+            Some(next) => Halt::Loop(next),
+            None => Halt::OutOfBounds((last as i64).saturating_add(instruction.argument())),
+        }
+    }
+
+    // Every cycle of the control-flow graph, each in execution order
+    // from its lowest address. Entering any of them never terminates.
+    pub fn loops(&self) -> Vec<Vec<usize>> {
+        let len = self.program.len();
+        // 0 unvisited, otherwise one more than the walk that visited it.
+        let mut walk = vec![0; len];
+        let mut loops = Vec::new();
+        for start in 0..len {
+            let mut address = start;
+            while address < len && walk[address] == 0 {
+                walk[address] = start + 1;
+                // A jump out of bounds ends the walk like termination.
+                address = successor(len, address, self.program[address]).unwrap_or(len);
+            }
+            if address < len && walk[address] == start + 1 {
+                // The walk closed a new cycle at `address`.
+                let mut cycle = vec![address];
+                let mut next = successor(len, address, self.program[address]).expect("cycle");
+                while next != address {
+                    cycle.push(next);
+                    next = successor(len, next, self.program[next]).expect("cycle");
+                }
+                let lowest = (0..cycle.len())
+                    .min_by_key(|i| cycle[*i])
+                    .expect("non-empty");
+                cycle.rotate_left(lowest);
+                loops.push(cycle);
+            }
+        }
+        loops.sort_unstable();
+        loops
+    }
+
+    // Instructions whose jmp/nop flip would change whether the program
+    // terminates. Only executed instructions can matter. For a looping
+    // program these are the repairs; for a terminating one, flips that
+    // lead somewhere that loops, or back onto the path before
+    // themselves.
+    pub fn critical_flips(&self) -> Vec<usize> {
+        let len = self.program.len();
+        let terminates = self.reaches_end[0];
+        let mut position = vec![None; len + 1];
+        for (i, address) in self.path.iter().enumerate() {
+            position[*address] = Some(i);
+        }
+        position[len] = Some(self.path.len());
+        // For addresses that reach the end, the first point on the
+        // path their run joins.
+        let mut joins = vec![0; len + 1];
+        if terminates {
+            let mut predecessors = vec![Vec::new(); len + 1];
+            for (address, instruction) in self.program.iter().enumerate() {
+                if let Some(next) = successor(len, address, *instruction) {
+                    predecessors[next].push(address);
+                }
+            }
+            joins[len] = self.path.len();
+            let mut pending = vec![len];
+            while let Some(address) = pending.pop() {
+                for previous in &predecessors[address] {
+                    joins[*previous] = position[*previous].unwrap_or(joins[address]);
+                    pending.push(*previous);
+                }
+            }
+        }
+        let mut critical: Vec<_> = self
+            .path
+            .iter()
+            .copied()
+            .filter(|&address| {
+                let flipped = match self.program[address].flip() {
+                    Some(flipped) => flipped,
+                    None => return false,
+                };
+                let flipped_terminates = successor(len, address, flipped).is_some_and(|next| {
+                    self.reaches_end[next] && (!terminates || Some(joins[next]) > position[address])
+                });
+                flipped_terminates != terminates
+            })
+            .collect();
+        critical.sort_unstable();
+        critical
+    }
 }
 
 #[cfg(test)]
@@ -216,5 +1207,423 @@ mod tests {
     fn test_part2() {
         assert_eq!(969, part2())
     }
+
+    const EXAMPLE: &str = "nop +0
+acc +1
+jmp +4
+acc +3
+jmp -3
+acc -99
+acc +1
+jmp -4
+acc +6
+";
+
+    #[test]
+    fn test_parse_program() {
+        let program = parse_program(EXAMPLE).expect("parse");
+        assert_eq!(program[5], Instruction::Acc(-99));
+        assert_eq!(program[7].flip(), Some(Instruction::Nop(-4)));
+        assert_eq!(disassemble(&program), EXAMPLE);
+        assert_eq!(
+            parse_program("nop +0\n\nmul +2"),
+            Err(ParseError::UnknownOpcode {
+                line: 3,
+                opcode: "mul".to_string()
+            })
+        );
+        assert_eq!(
+            parse_program("mul +x"),
+            Err(ParseError::UnknownOpcode {
+                line: 1,
+                opcode: "mul".to_string()
+            })
+        );
+        assert_eq!(
+            parse_program("acc +x"),
+            Err(ParseError::InvalidArgument {
+                line: 1,
+                argument: "+x".to_string()
+            })
+        );
+        assert_eq!(
+            parse_program("acc"),
+            Err(ParseError::MissingArgument { line: 1 })
+        );
+        assert_eq!(
+            parse_program("jmp +1 +2"),
+            Err(ParseError::TrailingInput {
+                line: 1,
+                input: "+2".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_machine() {
+        let program = parse_program(EXAMPLE).expect("parse");
+        let mut machine = Machine::new(program.clone());
+        assert_eq!(machine.run(None), Halt::Loop(1));
+        assert_eq!((machine.acc(), machine.steps()), (5, 7));
+
+        let mut machine = Machine::new(program.clone());
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), None);
+        assert_eq!((machine.ip(), machine.acc()), (2, 1));
+        assert_eq!(machine.run(Some(4)), Halt::StepLimit);
+        assert_eq!(machine.steps(), 4);
+
+        let looping = parse_program("nop +0\nacc +1\njmp -1").expect("parse");
+        let mut machine = Machine::new(looping.clone());
+        assert_eq!(machine.run(Some(2)), Halt::StepLimit);
+        assert_eq!(machine.run(Some(10)), Halt::Loop(1));
+        assert_eq!(machine.steps(), 3);
+        let mut machine = Machine::new(looping);
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.run(None), Halt::Loop(1));
+        assert_eq!(machine.acc(), 1);
+
+        let stuck = parse_program("nop +0\njmp -1").expect("parse");
+        let mut machine = Machine::new(stuck.clone());
+        assert_eq!(machine.run(None), Halt::Loop(0));
+        assert_eq!(
+            machine.patch(1, Instruction::Nop(-1)),
+            Some(Instruction::Jmp(-1))
+        );
+        assert!(machine.set_ip(0));
+        assert_eq!(machine.run(None), Halt::Terminated);
+        assert_eq!(machine.steps(), 4);
+        let mut machine = Machine::new(stuck);
+        assert_eq!(machine.run(None), Halt::Loop(0));
+        assert!(machine.set_ip(1));
+        assert_eq!(machine.run(None), Halt::Loop(1));
+        assert_eq!(machine.steps(), 4);
+
+        let mut fixed = program;
+        fixed[7] = Instruction::Nop(-4);
+        let mut machine = Machine::new(fixed);
+        assert_eq!(machine.run(None), Halt::Terminated);
+        assert_eq!(machine.acc(), 8);
+        assert_eq!(machine.step(), Some(Halt::Terminated));
+
+        let program = parse_program("acc +1\njmp -2").expect("parse");
+        let mut machine = Machine::new(program);
+        assert_eq!(machine.run(None), Halt::OutOfBounds(-1));
+        assert_eq!((machine.ip(), machine.acc()), (1, 1));
+        let program = parse_program("jmp +3\nnop +0").expect("parse");
+        assert_eq!(Machine::new(program).run(None), Halt::OutOfBounds(3));
+        let program = parse_program("jmp +9223372036854775807").expect("parse");
+        assert_eq!(Machine::new(program).run(None), Halt::OutOfBounds(i64::MAX));
+        assert_eq!(Machine::new(vec![]).run(None), Halt::Terminated);
+
+        let program = parse_program("acc +9223372036854775807\nacc +1").expect("parse");
+        let mut machine = Machine::new(program.clone());
+        assert_eq!(machine.run(None), Halt::Overflow);
+        assert_eq!((machine.ip(), machine.acc()), (1, i64::MAX));
+        assert_eq!(Analysis::new(&program).halt(), Halt::Overflow);
+        let mut debugger = Debugger::new(program);
+        assert_eq!(
+            debugger.execute("continue"),
+            Some("overflow at 1 with acc 9223372036854775807\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_debugger() {
+        let mut debugger = Debugger::new(parse_program(EXAMPLE).expect("parse"));
+        let mut run = |command: &str| debugger.execute(command).expect("output");
+        assert_eq!(run("break 6"), "breakpoint at 6\n");
+        assert_eq!(run("break acc"), "breakpoint on acc\n");
+        assert_eq!(run("break 9"), "no such address or opcode: 9\n");
+        assert_eq!(run("continue"), "breakpoint\nip 1 acc 0 | acc +1\n");
+        assert_eq!(run("delete acc"), "deleted acc\n");
+        assert_eq!(run("c"), "breakpoint\nip 6 acc 1 | acc +1\n");
+        assert_eq!(run("step 2"), "ip 3 acc 2 | acc +3\n");
+        assert_eq!(run("history 2"), "   6 acc +1\n   7 jmp -4\n");
+        assert_eq!(run("c"), "breakpoint\nip 6 acc 6 | acc +1\n");
+        assert_eq!(run("delete 6"), "deleted 6\n");
+        assert_eq!(run("c"), "loop at 6 with acc 11\n");
+        assert_eq!(run("break 6"), "breakpoint at 6\n");
+        assert_eq!(run("patch 7 nop -4"), "7: jmp -4 -> nop -4\n");
+        assert_eq!(run("acc 0"), "acc 0\n");
+        assert_eq!(run("ip 6"), "ip 6 acc 0 | acc +1\n");
+        assert_eq!(run("list").lines().nth(6), Some(">*   6 acc +1"));
+        assert_eq!(run("c"), "terminated with acc 7\n");
+        assert_eq!(run("breaks"), "6\n");
+        assert_eq!(run("jump"), "unknown command: jump\n");
+        assert_eq!(debugger.execute("quit"), None);
+
+        let mut output = Vec::new();
+        let input = "step\nacc\nquit\nstep\n".as_bytes();
+        debug(parse_program(EXAMPLE).expect("parse"), input, &mut output).expect("debug");
+        assert_eq!(
+            String::from_utf8(output).expect("utf8"),
+            "> ip 1 acc 0 | acc +1\n> acc 0\n> "
+        );
+    }
+
+    #[test]
+    fn test_trace() {
+        let program = parse_program(EXAMPLE).expect("parse");
+        let mut machine = Machine::new(program.clone()).with_trace();
+        assert_eq!(machine.run(None), Halt::Loop(1));
+        let trace = machine.trace().expect("trace");
+        let addresses: Vec<_> = trace.entries().iter().map(|e| e.address).collect();
+        assert_eq!(addresses, vec![0, 1, 2, 6, 7, 3, 4]);
+        assert_eq!(
+            trace.entries()[5],
+            TraceEntry {
+                step: 6,
+                address: 3,
+                instruction: Instruction::Acc(3),
+                acc_before: 2,
+                acc_after: 5
+            }
+        );
+        assert_eq!(trace.profile().get(&3), Some(&1));
+        assert!(trace
+            .to_text()
+            .starts_with("step address instruction acc\n   1       0 nop +0      0 -> 0\n"));
+        assert!(trace.to_text().ends_with("\naddress count\n      0     1\n      1     1\n      2     1\n      3     1\n      4     1\n      6     1\n      7     1\n"));
+        assert!(trace.to_json().starts_with(
+            "{\"trace\":[{\"step\":1,\"address\":0,\"instruction\":\"nop +0\",\"acc_before\":0,\"acc_after\":0},"
+        ));
+        assert!(trace
+            .to_json()
+            .ends_with("\"profile\":{\"0\":1,\"1\":1,\"2\":1,\"3\":1,\"4\":1,\"6\":1,\"7\":1}}"));
+
+        let mut machine = Machine::new(program).with_trace();
+        assert_eq!(machine.run(Some(20)), Halt::Loop(1));
+        let mut machine = Machine::new(parse_program("jmp +0").expect("parse")).with_trace();
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.step(), None);
+        assert_eq!(machine.trace().expect("trace").profile().get(&0), Some(&2));
+        assert_eq!(Machine::new(vec![]).trace(), None);
+    }
+
+    #[test]
+    fn test_repair() {
+        let program = parse_program(EXAMPLE).expect("parse");
+        assert_eq!(
+            repair(&program),
+            Some(Repair {
+                address: 7,
+                from: Instruction::Jmp(-4),
+                to: Instruction::Nop(-4),
+                acc: 8
+            })
+        );
+        assert_eq!(
+            terminating(&program),
+            vec![false, false, false, false, false, false, false, false, true, true]
+        );
+        let mut fixed = program.clone();
+        fixed[7] = Instruction::Nop(-4);
+        assert_eq!(repair(&fixed), None);
+        let program = parse_program("nop +3\njmp -1\njmp -5").expect("parse");
+        assert_eq!(
+            repair(&program).map(|r| (r.address, r.to)),
+            Some((0, Instruction::Jmp(3)))
+        );
+        let program = parse_program("jmp +0\nacc +1\njmp -1").expect("parse");
+        assert_eq!(repair(&program), None);
+        let program = parse_program("acc +9223372036854775807\nnop +3\njmp +0\nacc -1\nacc +1")
+            .expect("parse");
+        assert_eq!(
+            repair(&program),
+            Some(Repair {
+                address: 2,
+                from: Instruction::Jmp(0),
+                to: Instruction::Nop(0),
+                acc: i64::MAX
+            })
+        );
+    }
+
+    #[test]
+    fn test_repairs() {
+        let program = parse_program(EXAMPLE).expect("parse");
+        let flips = repairs(&program, &Mutations::default());
+        assert_eq!(flips, vec![repair(&program).expect("repair")]);
+        assert!(!ambiguous(&flips));
+
+        let mutations = Mutations {
+            opcodes: true,
+            arguments: None,
+        };
+        let found = repairs(&program, &mutations);
+        assert_eq!(
+            found
+                .iter()
+                .map(|r| (r.address, r.to, r.acc))
+                .collect::<Vec<_>>(),
+            vec![(7, Instruction::Nop(-4), 8), (7, Instruction::Acc(-4), 4)]
+        );
+        assert!(ambiguous(&found));
+        assert!(conflicting(&found));
+
+        let mutations = Mutations {
+            opcodes: false,
+            arguments: Some(-2..=2),
+        };
+        let found = repairs(&program, &mutations);
+        assert!(found
+            .iter()
+            .all(|r| r.to.opcode() == r.from.opcode() || r.from.flip() == Some(r.to)));
+        assert!(found.iter().any(|r| r.to == Instruction::Jmp(2)));
+
+        // Every offset is fine: only offsets reaching the end are tried,
+        // and each accumulator matches a real run.
+        let mutations = Mutations {
+            opcodes: true,
+            arguments: Some(i64::MIN..=i64::MAX),
+        };
+        let found = repairs(&program, &mutations);
+        let targets: Vec<_> = found
+            .iter()
+            .filter(|r| r.address == 0)
+            .map(|r| r.to)
+            .collect();
+        assert_eq!(targets, vec![Instruction::Jmp(8), Instruction::Jmp(9)]);
+        for r in &found {
+            let mut patched = program.clone();
+            patched[r.address] = r.to;
+            let mut machine = Machine::new(patched);
+            assert_eq!(machine.run(None), Halt::Terminated);
+            assert_eq!(machine.acc(), r.acc);
+        }
+        assert_eq!(found.len(), 16);
+
+        let mutations = Mutations {
+            opcodes: true,
+            arguments: Some(-10..=10),
+        };
+        let found = repairs(&program, &mutations);
+        let mut expected = Vec::new();
+        for (address, from) in program.iter().enumerate() {
+            let argument = from.argument();
+            let fixed = [
+                Instruction::Acc(argument),
+                Instruction::Jmp(argument),
+                Instruction::Nop(argument),
+            ];
+            for to in fixed
+                .iter()
+                .copied()
+                .chain((-10..=10).map(Instruction::Jmp))
+            {
+                let mut patched = program.clone();
+                patched[address] = to;
+                let mut machine = Machine::new(patched);
+                if to != *from
+                    && !expected.contains(&(address, to))
+                    && machine.run(None) == Halt::Terminated
+                {
+                    expected.push((address, to));
+                }
+            }
+        }
+        assert_eq!(found.len(), expected.len());
+        assert!(found.iter().all(|r| expected.contains(&(r.address, r.to))));
+        let same = [
+            repair(&program).expect("repair"),
+            repair(&program).expect("repair"),
+        ];
+        assert!(ambiguous(&same) && !conflicting(&same));
+
+        let mut fixed = program.clone();
+        fixed[7] = Instruction::Nop(-4);
+        assert_eq!(repairs(&fixed, &mutations), vec![]);
+
+        // Changes that would overflow the accumulator on the way don't
+        // count, even when it ends in range.
+        let mutations = Mutations {
+            opcodes: true,
+            arguments: None,
+        };
+        let program = parse_program("acc +9223372036854775806\njmp +0\nacc +1").expect("parse");
+        let found = repairs(&program, &mutations);
+        assert_eq!(
+            found
+                .iter()
+                .map(|r| (r.address, r.to, r.acc))
+                .collect::<Vec<_>>(),
+            vec![
+                (1, Instruction::Nop(0), i64::MAX),
+                (1, Instruction::Acc(0), i64::MAX)
+            ]
+        );
+        let program =
+            parse_program("acc +9223372036854775807\njmp +0\nacc +1\nacc -1").expect("parse");
+        assert_eq!(repairs(&program, &mutations), vec![]);
+    }
+
+    #[test]
+    fn test_analysis() {
+        let program = parse_program(EXAMPLE).expect("parse");
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.blocks(), &[0..1, 1..3, 3..5, 5..6, 6..8, 8..9]);
+        assert_eq!(analysis.block_of(4), Some(2));
+        assert_eq!(analysis.block_of(9), None);
+        assert_eq!(analysis.unreachable(), vec![5, 8]);
+        assert_eq!(analysis.halt(), Halt::Loop(1));
+        assert_eq!(analysis.loops(), vec![vec![1, 2, 6, 7, 3, 4]]);
+        assert_eq!(analysis.critical_flips(), vec![7]);
+        assert_eq!(
+            analysis.to_dot(),
+            r#"digraph {
+    0 [ label = "0: nop +0\l" ]
+    1 [ label = "1: acc +1\l2: jmp +4\l" ]
+    2 [ label = "3: acc +3\l4: jmp -3\l" ]
+    3 [ label = "5: acc -99\l" ]
+    4 [ label = "6: acc +1\l7: jmp -4\l" ]
+    5 [ label = "8: acc +6\l" ]
+    6 [ label = "exit" ]
+    0 -> 1 [ label = "next" ]
+    1 -> 4 [ label = "jmp" ]
+    2 -> 1 [ label = "jmp" ]
+    3 -> 4 [ label = "next" ]
+    4 -> 2 [ label = "jmp" ]
+    5 -> 6 [ label = "next" ]
 }
+"#
+        );
+
+        let mut fixed = program.clone();
+        fixed[7] = Instruction::Nop(-4);
+        let analysis = Analysis::new(&fixed);
+        assert_eq!(analysis.halt(), Halt::Terminated);
+        assert_eq!(analysis.loops(), Vec::<Vec<usize>>::new());
+        assert_eq!(analysis.critical_flips(), vec![0, 2, 7]);
 
+        // Cross-check against running every flip.
+        for program in &[program, fixed] {
+            let terminates =
+                |program: Vec<Instruction>| Machine::new(program).run(None) == Halt::Terminated;
+            let expected: Vec<_> = (0..program.len())
+                .filter(|&address| {
+                    program[address].flip().is_some_and(|to| {
+                        let mut flipped = program.clone();
+                        flipped[address] = to;
+                        terminates(flipped) != terminates(program.clone())
+                    })
+                })
+                .collect();
+            assert_eq!(Analysis::new(program).critical_flips(), expected);
+            assert_eq!(
+                Analysis::new(program).halt(),
+                Machine::new(program.clone()).run(None)
+            );
+        }
+
+        let program = parse_program("nop +0\njmp +0\njmp -7").expect("parse");
+        let analysis = Analysis::new(&program);
+        assert_eq!(analysis.halt(), Halt::Loop(1));
+        assert_eq!(analysis.loops(), vec![vec![1]]);
+        assert_eq!(analysis.unreachable(), vec![2]);
+        assert!(analysis.to_dot().contains("out of bounds (-5)"));
+        assert_eq!(Analysis::new(&[]).halt(), Halt::Terminated);
+    }
+}
@@ -0,0 +1,82 @@
+// Splitting line-oriented input into groups of lines, as in the
+// passport batches and customs forms.
+
+// Groups are separated by lines that are empty once trimmed, so CRLF
+// input and trailing whitespace don't merge neighbours; each line
+// comes with its 1-based number. An error is reported in place of the
+// group being read, whose remaining lines are skipped up to the next
+// separator; iteration ends if the lines fail again before yielding
+// one.
+pub(crate) fn groups<I, S, E>(lines: I) -> Groups<I>
+where
+    I: Iterator<Item = Result<S, E>>,
+    S: AsRef<str>,
+{
+    Groups {
+        lines,
+        line: 0,
+        skipping: false,
+        failed: false,
+        done: false,
+    }
+}
+
+// The groups of a string, which can't fail.
+pub(crate) fn text_groups(input: &str) -> impl Iterator<Item = Vec<(usize, &str)>> {
+    groups(input.lines().map(Ok::<_, std::convert::Infallible>))
+        .map(|group| group.unwrap_or_else(|never| match never {}))
+}
+
+pub(crate) struct Groups<I> {
+    lines: I,
+    line: usize,
+    skipping: bool,
+    failed: bool,
+    done: bool,
+}
+
+impl<I, S, E> Iterator for Groups<I>
+where
+    I: Iterator<Item = Result<S, E>>,
+    S: AsRef<str>,
+{
+    type Item = Result<Vec<(usize, S)>, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let mut group = Vec::new();
+        for text in &mut self.lines {
+            self.line += 1;
+            let text = match text {
+                Ok(text) => text,
+                Err(_) if self.failed => {
+                    self.done = true;
+                    return None;
+                }
+                Err(e) => {
+                    self.failed = true;
+                    self.skipping = true;
+                    return Some(Err(e));
+                }
+            };
+            self.failed = false;
+            if text.as_ref().trim().is_empty() {
+                self.skipping = false;
+                if !group.is_empty() {
+                    break;
+                }
+                continue;
+            }
+            if !self.skipping {
+                group.push((self.line, text));
+            }
+        }
+        if group.is_empty() {
+            None
+        } else {
+            Some(Ok(group))
+        }
+    }
+}
@@ -8,3 +8,5 @@ pub mod day6;
 pub mod day7;
 pub mod day8;
 pub mod day9;
+mod groups;
+mod json;